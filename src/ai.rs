@@ -0,0 +1,601 @@
+use rand::Rng;
+
+use crate::PLAYER;
+use crate::colors;
+use crate::game::{Game, MessageLog};
+use crate::object::{move_by, move_towards, mut_two, Object};
+use crate::map::is_blocked;
+use crate::noise::{notices_player, Alertness};
+use crate::path::{find_path, next_step, tile_cost, DIRECTIONS, STRAIGHT_COST};
+use crate::projectile::{line_of_fire, shoot, Projectile};
+use crate::scheduler::ActionKind;
+use crate::status::{add_status, tick_statuses, StatusKind};
+
+/// turns spent looking around where the player was last seen
+const SEARCH_TURNS: i32 = 5;
+/// how far from there it looks
+const SEARCH_RADIUS: f32 = 3.0;
+/// how far from its leader a follower goes while idle
+const FOLLOW_DISTANCE: f32 = 3.0;
+/// how long the followers stay enraged or frightened once the leader is dead
+const RAGE_TURNS: i32 = 20;
+const FEAR_TURNS: i32 = 10;
+/// how long a wounded monster runs away before it gets its courage back
+const FLEE_TURNS: i32 = 10;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Ai {
+    Basic,
+    /// keeps its distance and shoots, see `ai_ranged`
+    Ranged(RangedAttack),
+    /// Lost sight of the player: goes where it last saw them, then looks
+    /// around for some turns. The previous AI is back once the player is in
+    /// view again, see `ai_search`
+    Searching{previous_ai: Box<Ai>, x: i32, y: i32, num_turns: i32},
+    /// leads the pack `pack`, fighting with its own AI
+    Leader{pack: u32, ai: Box<Ai>},
+    /// Keeps near the leader of its pack while idle, and surrounds the
+    /// player with the others instead of queuing behind them, see
+    /// `ai_surround`
+    Follower{pack: u32, ai: Box<Ai>, on_leader_death: LeaderDeath},
+    /// Wounded below its morale, it runs away for some turns and then goes
+    /// back to the previous AI, see `check_morale`
+    Fleeing{previous_ai: Box<Ai>, num_turns: i32},
+}
+
+/// What the followers do once their leader is dead
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LeaderDeath {
+    /// run away for a while
+    Scatter,
+    /// fight harder
+    Enrage,
+}
+
+impl Ai {
+    /// a turn closer to shooting again, whatever it is doing
+    pub fn recharge(&mut self) {
+        match *self {
+            Ai::Ranged(ref mut attack) => attack.recharge = (attack.recharge - 1).max(0),
+            Ai::Searching{ref mut previous_ai, ..} | Ai::Fleeing{ref mut previous_ai, ..} => {
+                previous_ai.recharge()
+            }
+            Ai::Leader{ref mut ai, ..} | Ai::Follower{ref mut ai, ..} => ai.recharge(),
+            Ai::Basic => {}
+        }
+    }
+
+    /// the pack it leads, whatever it is doing
+    pub fn leader(&self) -> Option<u32> {
+        match *self {
+            Ai::Leader{pack, ..} => Some(pack),
+            Ai::Searching{ref previous_ai, ..} | Ai::Fleeing{ref previous_ai, ..} => {
+                previous_ai.leader()
+            }
+            _ => None,
+        }
+    }
+
+    /// the pack it follows and what it does when the leader dies, whatever
+    /// it is doing
+    pub fn follower(&self) -> Option<(u32, LeaderDeath)> {
+        match *self {
+            Ai::Follower{pack, on_leader_death, ..} => Some((pack, on_leader_death)),
+            Ai::Searching{ref previous_ai, ..} | Ai::Fleeing{ref previous_ai, ..} => {
+                previous_ai.follower()
+            }
+            _ => None,
+        }
+    }
+
+    /// the same AI, out of its pack
+    fn without_pack(self) -> Ai {
+        match self {
+            Ai::Leader{ai, ..} | Ai::Follower{ai, ..} => *ai,
+            Ai::Searching{previous_ai, x, y, num_turns} => {
+                Ai::Searching{previous_ai: Box::new(previous_ai.without_pack()), x, y,
+                              num_turns}
+            }
+            Ai::Fleeing{previous_ai, num_turns} => {
+                Ai::Fleeing{previous_ai: Box::new(previous_ai.without_pack()),
+                            num_turns}
+            }
+            ai => ai,
+        }
+    }
+}
+
+/// How a ranged monster shoots, and what it has left to shoot with
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RangedAttack {
+    pub projectile: Projectile,
+    /// how far it can shoot
+    pub range: i32,
+    /// how far from the player it tries to stay
+    pub distance: i32,
+    /// turns to wait after each shot
+    pub cooldown: i32,
+    /// turns left before it can shoot again
+    pub recharge: i32,
+    /// shots left, or None if it never runs out
+    pub ammo: Option<i32>,
+}
+
+/// Let the monster take one action. Monsters that have nothing to do wait,
+/// so every call spends some energy. Stunned monsters lose their turn,
+/// confused ones stumble around and frightened ones run away, whatever their
+/// AI, and only the monsters hunting the player follow their AI, see `noise`.
+pub fn ai_take_turn(monster_id: usize, game: &mut Game, objects: &mut [Object]) {
+    use Ai::*;
+    let energy = objects[monster_id].energy;
+    if let Some(ai) = objects[monster_id].ai.as_mut() {
+        ai.recharge();
+    }
+    if let Some((pack, reaction)) = objects[monster_id].ai.as_ref().and_then(Ai::follower) {
+        if leader_of(pack, objects).is_none() {
+            leader_died(monster_id, reaction, game, objects);
+        }
+    }
+    let alertness = objects[monster_id].alertness;
+    if alertness != Alertness::Hunting && notices_player(monster_id, objects, game) {
+        game.log.add(format!("The {} notices you!", objects[monster_id].name), colors::ORANGE);
+        objects[monster_id].alertness = Alertness::Hunting;
+    }
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let in_view = game.fov.is_in_fov(monster_x, monster_y);
+    if objects[monster_id].has_status(StatusKind::Stun) {
+        // nothing to do but wait
+    } else if objects[monster_id].has_status(StatusKind::Confusion) {
+        ai_confused(monster_id, game, objects);
+    } else if objects[monster_id].has_status(StatusKind::Fear) {
+        run_away(monster_id, game, objects);
+    } else if objects[monster_id].alertness != Alertness::Hunting {
+        ai_unaware(monster_id, game, objects);
+    } else if let Some(ai) = objects[monster_id].ai.take() {
+        let new_ai = match ai {
+            Fleeing{previous_ai, num_turns} => {
+                ai_flee(monster_id, game, objects, previous_ai, num_turns)
+            }
+            Searching{previous_ai, x, y, num_turns} => {
+                ai_search(monster_id, game, objects, previous_ai, (x, y), num_turns)
+            }
+            // hunting without having seen the player go, such as when hurt
+            // from afar: nowhere to look for them
+            ai if !in_view => {
+                objects[monster_id].alertness = Alertness::Wandering;
+                wander(monster_id, None, game, objects);
+                ai
+            }
+            ai => ai_in_view(monster_id, ai, game, objects),
+        };
+        // a monster can die on its own move, into lava or on a trap, and
+        // its corpse must not come back to life
+        if objects[monster_id].alive {
+            let searching = matches!(new_ai, Searching{..} | Fleeing{..});
+            objects[monster_id].ai = Some(new_ai);
+            // it saw the player at the start of its turn, and may have gone
+            // out of view since. See `lose_sight` for when the player goes
+            if in_view && !searching {
+                let last_seen = objects[PLAYER].pos();
+                lose_sight(&[monster_id], last_seen, objects, game);
+            }
+        }
+    }
+    if objects[monster_id].energy == energy {
+        objects[monster_id].spend_energy(ActionKind::Wait);
+    }
+    tick_statuses(monster_id, objects, game);
+}
+
+/// the monsters hunting the player that can see them now, and aren't
+/// searching for them
+pub fn hunters_in_view(objects: &[Object], game: &Game) -> Vec<usize> {
+    (0..objects.len())
+        .filter(|&id| {
+            let object = &objects[id];
+            object.alive && object.alertness == Alertness::Hunting &&
+                game.fov.is_in_fov(object.x, object.y) &&
+                object.ai.as_ref().is_some_and(|ai| {
+                    !matches!(*ai, Ai::Searching{..} | Ai::Fleeing{..})
+                })
+        })
+        .collect()
+}
+
+/// The hunters that saw the player at `(x, y)` and can't see them any more
+/// start searching there
+pub fn lose_sight(hunters: &[usize], (x, y): (i32, i32), objects: &mut [Object],
+                  game: &Game) {
+    for &id in hunters {
+        if !objects[id].alive || game.fov.is_in_fov(objects[id].x, objects[id].y) {
+            continue;
+        }
+        if let Some(ai) = objects[id].ai.take() {
+            objects[id].ai = Some(Ai::Searching{previous_ai: Box::new(ai), x, y,
+                                                num_turns: SEARCH_TURNS});
+        }
+    }
+}
+
+/// Take the turn with the player in view
+fn ai_in_view(monster_id: usize, ai: Ai, game: &mut Game, objects: &mut [Object]) -> Ai {
+    use Ai::*;
+    match ai {
+        Basic => ai_basic(monster_id, game, objects),
+        Ranged(attack) => Ranged(ai_ranged(monster_id, attack, game, objects)),
+        Leader{pack, ai} => {
+            Leader{pack, ai: Box::new(ai_in_view(monster_id, *ai, game, objects))}
+        }
+        Follower{pack, ai, on_leader_death} => {
+            // those that fight in melee gather around the player
+            let ai = match *ai {
+                Basic => ai_surround(monster_id, game, objects),
+                ai => ai_in_view(monster_id, ai, game, objects),
+            };
+            Follower{pack, ai: Box::new(ai), on_leader_death}
+        }
+        // never nested
+        Searching{..} | Fleeing{..} => ai,
+    }
+}
+
+/// Make the monster flee if it is wounded below its morale, unless it
+/// already does. Its AI is back once it has fled long enough
+pub fn check_morale(monster: &mut Object, game: &mut Game) {
+    let fighter = match monster.fighter {
+        Some(fighter) if monster.alive => fighter,
+        _ => return,
+    };
+    let max_hp = monster.max_hp(game);
+    if fighter.hp * 100 >= fighter.morale * max_hp {
+        return;
+    }
+    match monster.ai.take() {
+        Some(Ai::Fleeing{previous_ai, num_turns}) => {
+            monster.ai = Some(Ai::Fleeing{previous_ai, num_turns});
+        }
+        Some(previous_ai) => {
+            monster.ai = Some(Ai::Fleeing{previous_ai: Box::new(previous_ai),
+                                          num_turns: FLEE_TURNS});
+            if game.fov.is_in_fov(monster.x, monster.y) {
+                game.log.add(format!("The {} flees in terror!", monster.name),
+                             colors::LIGHT_YELLOW);
+            }
+        }
+        None => {}
+    }
+}
+
+fn ai_flee(monster_id: usize, game: &mut Game, objects: &mut [Object],
+           previous_ai: Box<Ai>, num_turns: i32) -> Ai {
+    // still afraid
+    if num_turns > 0 {
+        run_away(monster_id, game, objects);
+        Ai::Fleeing{previous_ai, num_turns: num_turns - 1}
+    } else {    // restore the previous AI
+        let (x, y) = objects[monster_id].pos();
+        if game.fov.is_in_fov(x, y) {
+            game.log.add(format!("The {} turns to fight!", objects[monster_id].name),
+                         colors::ORANGE);
+        }
+        *previous_ai
+    }
+}
+
+fn ai_basic(monster_id: usize, game: &mut Game, objects: &mut [Object]) -> Ai {
+    // a basic monster takes its turn. If you can see it, it can see you
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    if game.fov.is_in_fov(monster_x, monster_y) {
+        if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
+            // move towards player if far away, around walls and other monsters
+            let (player_x, player_y) = objects[PLAYER].pos();
+            let movement = objects[monster_id].movement;
+            let kind = match next_step((monster_x, monster_y), (player_x, player_y), movement,
+                                       &game.map, objects) {
+                Some((x, y)) => move_by(monster_id, x - monster_x, y - monster_y, game, objects),
+                // no way through, get as close as the walls allow
+                None => move_towards(monster_id, player_x, player_y, game, objects),
+            };
+            objects[monster_id].spend_energy(kind);
+        } else if objects[PLAYER].fighter.is_some_and(|f| f.hp > 0) {
+            // close enough to attack! (if the player is still alive.)
+            let (monster, player) = mut_two(monster_id, PLAYER, objects);
+            monster.attack(player, game);
+            monster.spend_energy(ActionKind::Attack);
+        }
+    }
+    Ai::Basic
+}
+
+/// Shoot whenever the player is in range with nothing in the way, and move to
+/// keep at the preferred distance otherwise. Out of ammo, it fights like a
+/// basic monster.
+fn ai_ranged(monster_id: usize, mut attack: RangedAttack, game: &mut Game,
+             objects: &mut [Object]) -> RangedAttack {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    if !game.fov.is_in_fov(monster_x, monster_y) {
+        return attack;
+    }
+    let ready = attack.recharge == 0;
+    if attack.ammo == Some(0) {
+        ai_basic(monster_id, game, objects);
+        return attack;
+    }
+
+    let distance = objects[monster_id].distance_to(&objects[PLAYER]);
+    let player_alive = objects[PLAYER].fighter.is_some_and(|f| f.hp > 0);
+    let clear_shot = distance <= attack.range as f32 &&
+        line_of_fire((monster_x, monster_y), PLAYER, &game.map, objects);
+    if clear_shot && ready && player_alive {
+        shoot(monster_id, objects[PLAYER].pos(), &attack.projectile, objects, game);
+        objects[monster_id].spend_energy(ActionKind::Attack);
+        // counted down from the next turn on, see `Ai::recharge`
+        attack.recharge = attack.cooldown + 1;
+        attack.ammo = attack.ammo.map(|ammo| ammo - 1);
+        return attack;
+    }
+
+    // keep to the preferred distance, or get closer for a clear shot
+    let wanted = if clear_shot || distance < attack.distance as f32 {
+        attack.distance * STRAIGHT_COST
+    } else {
+        0
+    };
+    let to_player = game.chase.to_player(objects[monster_id].movement);
+    match to_player.towards_value(monster_x, monster_y, wanted) {
+        Some((x, y)) => {
+            let kind = move_by(monster_id, x - monster_x, y - monster_y, game, objects);
+            objects[monster_id].spend_energy(kind);
+        }
+        // cornered next to the player
+        None if distance < 2.0 && player_alive => {
+            let (monster, player) = mut_two(monster_id, PLAYER, objects);
+            monster.attack(player, game);
+            monster.spend_energy(ActionKind::Attack);
+        }
+        None => {}
+    }
+    attack
+}
+
+/// A monster that doesn't know about the player sleeps, goes to see what
+/// made the noise it heard, or wanders around
+fn ai_unaware(monster_id: usize, game: &mut Game, objects: &mut [Object]) {
+    match objects[monster_id].alertness {
+        Alertness::Alerted { x, y } => {
+            // there, or no way there: nothing to see after all
+            if !step_towards(monster_id, (x, y), game, objects) {
+                objects[monster_id].alertness = Alertness::Wandering;
+            }
+        }
+        Alertness::Wandering => {
+            // followers keep near their leader
+            let leader = objects[monster_id].ai.as_ref()
+                .and_then(Ai::follower)
+                .and_then(|(pack, _)| leader_of(pack, objects))
+                .map(|leader_id| objects[leader_id].pos());
+            match leader {
+                Some((x, y)) if objects[monster_id].distance(x, y) > FOLLOW_DISTANCE => {
+                    if !step_towards(monster_id, (x, y), game, objects) {
+                        wander(monster_id, None, game, objects);
+                    }
+                }
+                Some(leader) => wander(monster_id, Some((leader, FOLLOW_DISTANCE)), game, objects),
+                None => wander(monster_id, None, game, objects),
+            }
+        }
+        Alertness::Asleep | Alertness::Hunting => {}
+    }
+}
+
+/// Go where the player was last seen, then look around there until the
+/// turns run out, and give up. Seeing the player again brings the previous
+/// AI back, which takes the turn.
+fn ai_search(monster_id: usize, game: &mut Game, objects: &mut [Object],
+             previous_ai: Box<Ai>, (x, y): (i32, i32), num_turns: i32) -> Ai {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    if game.fov.is_in_fov(monster_x, monster_y) {
+        return ai_in_view(monster_id, *previous_ai, game, objects);
+    }
+    if num_turns <= 0 {
+        objects[monster_id].alertness = Alertness::Wandering;
+        return *previous_ai;
+    }
+
+    let movement = objects[monster_id].movement;
+    // once there, anywhere around will do
+    let distance = objects[monster_id].distance(x, y);
+    let arrived = distance < 2.0 || (num_turns < SEARCH_TURNS && distance <= SEARCH_RADIUS);
+    match next_step((monster_x, monster_y), (x, y), movement, &game.map, objects) {
+        Some((step_x, step_y)) if !arrived => {
+            let kind = move_by(monster_id, step_x - monster_x, step_y - monster_y, game,
+                               objects);
+            objects[monster_id].spend_energy(kind);
+            Ai::Searching{previous_ai, x, y, num_turns}
+        }
+        // next to it, or no way there: look around
+        _ => {
+            wander(monster_id, Some(((x, y), SEARCH_RADIUS)), game, objects);
+            Ai::Searching{previous_ai, x, y, num_turns: num_turns - 1}
+        }
+    }
+}
+
+/// Take a random step anywhere free, but not into danger, and not farther
+/// from `around` than the distance given with it
+fn wander(monster_id: usize, around: Option<((i32, i32), f32)>, game: &mut Game,
+          objects: &mut [Object]) {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let movement = objects[monster_id].movement;
+    let steps: Vec<_> = DIRECTIONS.iter()
+        .cloned()
+        .filter(|&(dx, dy)| {
+            let (x, y) = (monster_x + dx, monster_y + dy);
+            let near = around.is_none_or(|((around_x, around_y), distance)| {
+                (((x - around_x).pow(2) + (y - around_y).pow(2)) as f32).sqrt() <= distance
+            });
+            near && !is_blocked(x, y, movement, &game.map, objects) &&
+                tile_cost(&game.map[x as usize][y as usize], movement)
+                    .is_some_and(|cost| cost <= STRAIGHT_COST)
+        })
+        .collect();
+    if !steps.is_empty() {
+        let (dx, dy) = steps[game.rng.gen_range(0, steps.len())];
+        let kind = move_by(monster_id, dx, dy, game, objects);
+        objects[monster_id].spend_energy(kind);
+    }
+}
+
+/// Take the first step of the way to the tile, returning false if there is
+/// no way or it is already there
+fn step_towards(monster_id: usize, (x, y): (i32, i32), game: &mut Game,
+                objects: &mut [Object]) -> bool {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let movement = objects[monster_id].movement;
+    match next_step((monster_x, monster_y), (x, y), movement, &game.map, objects) {
+        Some((step_x, step_y)) => {
+            let kind = move_by(monster_id, step_x - monster_x, step_y - monster_y, game,
+                               objects);
+            objects[monster_id].spend_energy(kind);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Head for the closest free tile next to the player, so that the pack
+/// comes from all sides and goes around those already fighting. With none
+/// left to get to, it waits its turn
+fn ai_surround(monster_id: usize, game: &mut Game, objects: &mut [Object]) -> Ai {
+    if objects[monster_id].distance_to(&objects[PLAYER]) < 2.0 {
+        return ai_basic(monster_id, game, objects);
+    }
+    let from = objects[monster_id].pos();
+    let (player_x, player_y) = objects[PLAYER].pos();
+    let movement = objects[monster_id].movement;
+    let closest = DIRECTIONS.iter()
+        .map(|&(dx, dy)| (player_x + dx, player_y + dy))
+        .filter(|&(x, y)| !is_blocked(x, y, movement, &game.map, objects))
+        .filter_map(|tile| find_path(from, tile, movement, &game.map, objects))
+        .min_by_key(|path| path.len());
+    if let Some((x, y)) = closest.and_then(|path| path.first().cloned()) {
+        let kind = move_by(monster_id, x - from.0, y - from.1, game, objects);
+        objects[monster_id].spend_energy(kind);
+    }
+    Ai::Basic
+}
+
+/// the leader of the pack, while alive
+fn leader_of(pack: u32, objects: &[Object]) -> Option<usize> {
+    objects.iter().position(|object| {
+        object.alive && object.ai.as_ref().and_then(Ai::leader) == Some(pack)
+    })
+}
+
+/// The leader is dead: the follower goes on alone, enraged or frightened off
+fn leader_died(monster_id: usize, reaction: LeaderDeath, game: &mut Game,
+               objects: &mut [Object]) {
+    let ai = objects[monster_id].ai.take().map(Ai::without_pack);
+    objects[monster_id].ai = ai;
+    match reaction {
+        LeaderDeath::Enrage => {
+            objects[monster_id].alertness = Alertness::Hunting;
+            add_status(monster_id, StatusKind::Rage, RAGE_TURNS, 1, objects, game);
+        }
+        LeaderDeath::Scatter => {
+            add_status(monster_id, StatusKind::Fear, FEAR_TURNS, 1, objects, game);
+        }
+    }
+}
+
+/// Run away from the player, and fight back when cornered
+fn run_away(monster_id: usize, game: &mut Game, objects: &mut [Object]) {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let movement = objects[monster_id].movement;
+    let away = game.chase.from_player(movement).downhill(monster_x, monster_y)
+        .filter(|&(x, y)| !is_blocked(x, y, movement, &game.map, objects));
+    match away {
+        Some((x, y)) => {
+            let kind = move_by(monster_id, x - monster_x, y - monster_y, game, objects);
+            objects[monster_id].spend_energy(kind);
+        }
+        None if objects[monster_id].distance_to(&objects[PLAYER]) < 2.0 &&
+            objects[PLAYER].fighter.is_some_and(|f| f.hp > 0) => {
+            let (monster, player) = mut_two(monster_id, PLAYER, objects);
+            monster.attack(player, game);
+            monster.spend_energy(ActionKind::Attack);
+        }
+        None => {}
+    }
+}
+
+fn ai_confused(monster_id: usize, game: &mut Game, objects: &mut [Object]) {
+    // move in a random direction
+    let kind = move_by(monster_id,
+                       game.rng.gen_range(-1, 2),
+                       game.rng.gen_range(-1, 2),
+                       game,
+                       objects);
+    objects[monster_id].spend_energy(kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::combat::DamageType;
+    use crate::testing::{add_hunter, empty_level, next_to_player, out_of_view};
+
+    #[test]
+    fn hunter_does_not_go_back_to_an_old_search() {
+        let (mut objects, mut game) = empty_level();
+        let tile = next_to_player(&objects, &game);
+        let orc = add_hunter("orc", tile, &mut objects, &game);
+        // it sees the player, then searches for them elsewhere until it gives up
+        ai_take_turn(orc, &mut game, &mut objects);
+        let (x, y) = out_of_view(&objects, &game);
+        objects[orc].set_pos(x, y);
+        objects[orc].ai = Some(Ai::Searching{previous_ai: Box::new(Ai::Basic), x, y,
+                                             num_turns: 0});
+        ai_take_turn(orc, &mut game, &mut objects);
+        assert_eq!(objects[orc].alertness, Alertness::Wandering);
+
+        // hurt from out of view, it hunts again with nowhere to look
+        objects[orc].take_damage(1, DamageType::Physical, &mut game);
+        assert_eq!(objects[orc].alertness, Alertness::Hunting);
+        ai_take_turn(orc, &mut game, &mut objects);
+        assert_eq!(objects[orc].alertness, Alertness::Wandering);
+        assert_eq!(objects[orc].ai, Some(Ai::Basic));
+    }
+
+    #[test]
+    fn hunter_that_never_saw_the_player_wanders() {
+        let (mut objects, mut game) = empty_level();
+        let tile = out_of_view(&objects, &game);
+        let orc = add_hunter("orc", tile, &mut objects, &game);
+        ai_take_turn(orc, &mut game, &mut objects);
+        assert_eq!(objects[orc].alertness, Alertness::Wandering);
+        assert_eq!(objects[orc].ai, Some(Ai::Basic));
+    }
+
+    #[test]
+    fn ranged_attacks_recharge_out_of_view() {
+        let (mut objects, mut game) = empty_level();
+        let tile = out_of_view(&objects, &game);
+        let shaman = add_hunter("kobold shaman", tile, &mut objects, &game);
+        let cooldown = match objects[shaman].ai {
+            Some(Ai::Ranged(ref mut attack)) => {
+                attack.recharge = attack.cooldown + 1;
+                attack.cooldown
+            }
+            ref ai => panic!("not a ranged AI: {:?}", ai),
+        };
+        // whether it hunts or wanders, every turn counts
+        for _ in 0..=cooldown {
+            ai_take_turn(shaman, &mut game, &mut objects);
+        }
+        match objects[shaman].ai {
+            Some(Ai::Ranged(ref attack)) => assert_eq!(attack.recharge, 0),
+            ref ai => panic!("not a ranged AI: {:?}", ai),
+        }
+    }
+}
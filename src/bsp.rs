@@ -0,0 +1,73 @@
+//! Binary space partition generator: the map is cut in two again and again,
+//! a room is dug in every piece that can't be cut any more, and the rooms of
+//! the two halves of each cut are joined. Rooms end up spread over the whole
+//! map, with no big empty areas.
+
+use rand::Rng;
+
+use crate::map::{Tile, Map, MAP_HEIGHT, MAP_WIDTH};
+use crate::mapgen::{connect, create_room, Layout, MapGenerator, Rect};
+use crate::rng::GameRng;
+
+/// pieces smaller than twice this are not cut any more
+const MIN_LEAF_SIZE: i32 = 9;
+/// including the walls around the room
+const MIN_ROOM_SIZE: i32 = 5;
+
+pub struct BspGenerator;
+
+impl MapGenerator for BspGenerator {
+    fn generate(&self, rng: &mut GameRng) -> Layout {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        let mut rooms = vec![];
+        // the last row and column stay walls, as rooms never carve their edges
+        let whole_map = Rect::new(0, 0, MAP_WIDTH - 1, MAP_HEIGHT - 1);
+        split(whole_map, &mut map, &mut rooms, rng);
+        Layout::from_rooms(map, &rooms)
+    }
+}
+
+/// Fill `area` with rooms joined together, and return one of them for the
+/// caller to join to the rest of the map
+fn split(area: Rect, map: &mut Map, rooms: &mut Vec<Rect>, rng: &mut GameRng) -> Rect {
+    let can_cut_across = area.width() >= 2 * MIN_LEAF_SIZE;
+    let can_cut_down = area.height() >= 2 * MIN_LEAF_SIZE;
+    if !can_cut_across && !can_cut_down {
+        // a leaf: dig a room of random size somewhere inside
+        let w = rng.gen_range(MIN_ROOM_SIZE, area.width() + 1);
+        let h = rng.gen_range(MIN_ROOM_SIZE, area.height() + 1);
+        let x = rng.gen_range(area.x1, area.x2 - w + 1);
+        let y = rng.gen_range(area.y1, area.y2 - h + 1);
+        let room = Rect::new(x, y, w, h);
+        create_room(room, map);
+        rooms.push(room);
+        return room;
+    }
+
+    // cut the long side, so pieces stay roughly square
+    let vertical_cut = if can_cut_across && can_cut_down {
+        if area.width() > area.height() * 5 / 4 {
+            true
+        } else if area.height() > area.width() * 5 / 4 {
+            false
+        } else {
+            rng.gen()
+        }
+    } else {
+        can_cut_across
+    };
+    let (first, second) = if vertical_cut {
+        let cut = rng.gen_range(MIN_LEAF_SIZE, area.width() - MIN_LEAF_SIZE + 1);
+        (Rect::new(area.x1, area.y1, cut, area.height()),
+         Rect::new(area.x1 + cut, area.y1, area.width() - cut, area.height()))
+    } else {
+        let cut = rng.gen_range(MIN_LEAF_SIZE, area.height() - MIN_LEAF_SIZE + 1);
+        (Rect::new(area.x1, area.y1, area.width(), cut),
+         Rect::new(area.x1, area.y1 + cut, area.width(), area.height() - cut))
+    };
+    let first_room = split(first, map, rooms, rng);
+    let second_room = split(second, map, rooms, rng);
+    connect(first_room.center(), second_room.center(), map, rng);
+
+    if rng.gen() { first_room } else { second_room }
+}
@@ -0,0 +1,145 @@
+//! Cave generator: random noise smoothed with cellular automata into open
+//! caverns. Only the biggest connected cave is kept, every other pocket is
+//! filled in, so everything on the level can be reached.
+
+use rand::Rng;
+
+use crate::dijkstra::DijkstraMap;
+use crate::map::{Map, Tile, MAP_HEIGHT, MAP_WIDTH};
+use crate::mapgen::{Layout, MapGenerator, Region};
+use crate::object::Movement;
+use crate::path::DIRECTIONS;
+use crate::rng::GameRng;
+
+/// chance for each tile to start as a wall, in percent
+const INITIAL_WALLS: u32 = 45;
+const SMOOTHING_STEPS: u32 = 5;
+/// a tile is a wall after a step if this many of the nine tiles around it,
+/// itself included, are walls
+const WALL_NEIGHBOURS: usize = 5;
+/// caves smaller than this part of the map are thrown away and grown again
+const MIN_OPEN_TILES: usize = (MAP_WIDTH * MAP_HEIGHT / 3) as usize;
+/// the cave is cut in squares this big to spread monsters and items
+const SECTOR_SIZE: i32 = 12;
+/// sectors with less of the cave than this get nothing
+const MIN_SECTOR_TILES: usize = 12;
+
+pub struct CaveGenerator;
+
+impl MapGenerator for CaveGenerator {
+    fn generate(&self, rng: &mut GameRng) -> Layout {
+        loop {
+            let mut map = random_walls(rng);
+            for _ in 0..SMOOTHING_STEPS {
+                map = smooth(&map);
+            }
+            let cave = keep_biggest_cave(&mut map);
+            if cave.len() < MIN_OPEN_TILES {
+                continue;
+            }
+
+            // arrive anywhere in the cave, the stairs down are as far as it goes
+            let start = cave[rng.gen_range(0, cave.len())];
+            let distance = DijkstraMap::new(&map, &[start], Movement::Walk);
+            let exit = *cave.iter()
+                .max_by_key(|&&(x, y)| distance.value(x, y).unwrap_or(0))
+                .unwrap();
+
+            return Layout { regions: sectors(&cave), map, start, exit };
+        }
+    }
+}
+
+fn random_walls(rng: &mut GameRng) -> Map {
+    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    // the edges of the map always stay walls
+    for x in 1..(MAP_WIDTH - 1) {
+        for y in 1..(MAP_HEIGHT - 1) {
+            if rng.gen_range(0, 100) >= INITIAL_WALLS {
+                map[x as usize][y as usize] = Tile::empty();
+            }
+        }
+    }
+    map
+}
+
+/// one step of the automaton: tiles in mostly walls become walls, the others
+/// open up
+fn smooth(map: &Map) -> Map {
+    let mut smoothed = map.clone();
+    for x in 1..(MAP_WIDTH - 1) {
+        for y in 1..(MAP_HEIGHT - 1) {
+            let walls = DIRECTIONS.iter()
+                .filter(|&&(dx, dy)| map[(x + dx) as usize][(y + dy) as usize].blocked)
+                .count() + map[x as usize][y as usize].blocked as usize;
+            smoothed[x as usize][y as usize] = if walls >= WALL_NEIGHBOURS {
+                Tile::wall()
+            } else {
+                Tile::empty()
+            };
+        }
+    }
+    smoothed
+}
+
+/// Fill every cave but the biggest with walls, and return the tiles of the
+/// one left
+fn keep_biggest_cave(map: &mut Map) -> Vec<(i32, i32)> {
+    let mut seen = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut caves = vec![];
+    for x in 0..MAP_WIDTH {
+        for y in 0..MAP_HEIGHT {
+            if !map[x as usize][y as usize].blocked && !seen[x as usize][y as usize] {
+                caves.push(flood_fill((x, y), map, &mut seen));
+            }
+        }
+    }
+    // the first biggest, so the same seed always keeps the same cave
+    let biggest = caves.iter()
+        .enumerate()
+        .max_by_key(|&(index, cave)| (cave.len(), -(index as i64)))
+        .map_or(0, |(index, _)| index);
+    for (index, cave) in caves.iter().enumerate() {
+        if index != biggest {
+            for &(x, y) in cave {
+                map[x as usize][y as usize] = Tile::wall();
+            }
+        }
+    }
+    if caves.is_empty() { vec![] } else { caves.swap_remove(biggest) }
+}
+
+/// all the open tiles that can be walked to from `start`
+fn flood_fill(start: (i32, i32), map: &Map, seen: &mut [Vec<bool>]) -> Vec<(i32, i32)> {
+    let mut tiles = vec![];
+    let mut stack = vec![start];
+    seen[start.0 as usize][start.1 as usize] = true;
+    while let Some((x, y)) = stack.pop() {
+        tiles.push((x, y));
+        for &(dx, dy) in DIRECTIONS.iter() {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= MAP_WIDTH || ny >= MAP_HEIGHT {
+                continue;
+            }
+            if !map[nx as usize][ny as usize].blocked && !seen[nx as usize][ny as usize] {
+                seen[nx as usize][ny as usize] = true;
+                stack.push((nx, ny));
+            }
+        }
+    }
+    tiles
+}
+
+/// cut the cave in squares, each one a region to spawn things in
+fn sectors(cave: &[(i32, i32)]) -> Vec<Region> {
+    let columns = (MAP_WIDTH + SECTOR_SIZE - 1) / SECTOR_SIZE;
+    let rows = (MAP_HEIGHT + SECTOR_SIZE - 1) / SECTOR_SIZE;
+    let mut sectors: Vec<Vec<(i32, i32)>> = vec![vec![]; (columns * rows) as usize];
+    for &(x, y) in cave {
+        sectors[(x / SECTOR_SIZE * rows + y / SECTOR_SIZE) as usize].push((x, y));
+    }
+    sectors.into_iter()
+        .filter(|tiles| tiles.len() >= MIN_SECTOR_TILES)
+        .map(|tiles| Region { tiles })
+        .collect()
+}
@@ -0,0 +1,306 @@
+//! How melee attacks are decided. An attack first has to hit, which depends
+//! on the attacker's accuracy against the defender's evasion. The damage is
+//! the attacker's power plus a roll of its weapon's dice, doubled on a
+//! critical hit, and armor takes its defense off that without ever stopping
+//! the blow completely. Everything random comes from the given `Rng`, so a
+//! seeded one gives the same fight every time.
+//!
+//! Damage of any source has a type, and creatures can resist some types or
+//! be vulnerable to them, see `Resistances`.
+
+use std::fmt;
+
+use rand::Rng;
+
+/// chance in percent to hit when accuracy and evasion are equal
+const BASE_HIT_CHANCE: i32 = 80;
+/// change to the chance to hit for each point of accuracy over evasion
+const HIT_CHANCE_PER_POINT: i32 = 5;
+/// there is always some chance to hit, and to miss
+const MIN_HIT_CHANCE: i32 = 5;
+const MAX_HIT_CHANCE: i32 = 95;
+/// chance in percent for a hit to be critical
+const CRITICAL_CHANCE: i32 = 5;
+const CRITICAL_MULTIPLIER: i32 = 2;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum DamageType {
+    #[default]
+    Physical,
+    Fire,
+    Lightning,
+    Cold,
+    Poison,
+}
+
+impl DamageType {
+    pub fn parse(text: &str) -> Option<DamageType> {
+        use DamageType::*;
+        match text {
+            "physical" => Some(Physical),
+            "fire" => Some(Fire),
+            "lightning" => Some(Lightning),
+            "cold" => Some(Cold),
+            "poison" => Some(Poison),
+            _ => None,
+        }
+    }
+
+    /// as in "a bolt of ..."
+    pub fn noun(self) -> &'static str {
+        use DamageType::*;
+        match self {
+            Physical => "force",
+            Fire => "fire",
+            Lightning => "lightning",
+            Cold => "frost",
+            Poison => "poison",
+        }
+    }
+}
+
+/// How much of each type of damage is kept off, in percent: 50 halves it,
+/// 100 or more makes immune, and -50 is a vulnerability that adds half
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Resistances {
+    pub physical: i32,
+    pub fire: i32,
+    pub lightning: i32,
+    pub cold: i32,
+    pub poison: i32,
+}
+
+impl Resistances {
+    pub fn get(&self, kind: DamageType) -> i32 {
+        use DamageType::*;
+        match kind {
+            Physical => self.physical,
+            Fire => self.fire,
+            Lightning => self.lightning,
+            Cold => self.cold,
+            Poison => self.poison,
+        }
+    }
+
+    /// both together, such as a creature's own and its equipment's
+    pub fn combine(self, other: Resistances) -> Resistances {
+        Resistances {
+            physical: self.physical + other.physical,
+            fire: self.fire + other.fire,
+            lightning: self.lightning + other.lightning,
+            cold: self.cold + other.cold,
+            poison: self.poison + other.poison,
+        }
+    }
+}
+
+/// the damage left after a resistance, rounded to the nearest
+pub fn resist_damage(damage: i32, resistance: i32) -> i32 {
+    if resistance >= 100 || damage <= 0 {
+        return 0;
+    }
+    (damage * (100 - resistance) + 50) / 100
+}
+
+/// Dice such as "2d4+1": two four-sided dice, plus one
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Dice {
+    pub count: i32,
+    pub sides: i32,
+    pub bonus: i32,
+}
+
+impl Dice {
+    /// read dice written as "1d6", "2d4+1" or "1d8-1"
+    pub fn parse(text: &str) -> Option<Dice> {
+        let d = text.find('d')?;
+        let (count, rest) = (&text[..d], &text[d + 1..]);
+        let (sides, bonus) = match rest.find(['+', '-']) {
+            Some(sign) => {
+                let bonus = digits(&rest[sign + 1..])?;
+                (&rest[..sign], if rest[sign..].starts_with('-') { -bonus } else { bonus })
+            }
+            None => (rest, 0),
+        };
+        let dice = Dice { count: digits(count)?, sides: digits(sides)?, bonus };
+        if dice.count < 1 || dice.sides < 1 {
+            return None;
+        }
+        Some(dice)
+    }
+
+    pub fn roll<R: Rng>(self, rng: &mut R) -> i32 {
+        let rolled: i32 = (0..self.count).map(|_| rng.gen_range(1, self.sides + 1)).sum();
+        rolled + self.bonus
+    }
+}
+
+/// a number written with digits only, without a sign
+fn digits(text: &str) -> Option<i32> {
+    if text.is_empty() || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+impl fmt::Display for Dice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bonus {
+            0 => write!(f, "{}d{}", self.count, self.sides),
+            bonus if bonus > 0 => write!(f, "{}d{}+{}", self.count, self.sides, bonus),
+            bonus => write!(f, "{}d{}{}", self.count, self.sides, bonus),
+        }
+    }
+}
+
+/// What the attacker brings to a fight
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attack {
+    pub accuracy: i32,
+    pub power: i32,
+    /// the weapon's, if any
+    pub dice: Option<Dice>,
+}
+
+/// What the defender has against it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Defense {
+    pub evasion: i32,
+    pub armor: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttackResult {
+    Miss,
+    Hit { damage: i32 },
+    Critical { damage: i32 },
+}
+
+/// the chance in percent for an attack to hit
+pub fn hit_chance(accuracy: i32, evasion: i32) -> i32 {
+    let chance = BASE_HIT_CHANCE + HIT_CHANCE_PER_POINT * (accuracy - evasion);
+    chance.clamp(MIN_HIT_CHANCE, MAX_HIT_CHANCE)
+}
+
+/// the damage left once armor took its part, at least 1
+pub fn reduce_damage(damage: i32, armor: i32) -> i32 {
+    (damage - armor.max(0)).max(1)
+}
+
+pub fn roll_attack<R: Rng>(attack: Attack, defense: Defense, rng: &mut R) -> AttackResult {
+    if rng.gen_range(0, 100) >= hit_chance(attack.accuracy, defense.evasion) {
+        return AttackResult::Miss;
+    }
+    let damage = attack.power + attack.dice.map_or(0, |dice| dice.roll(rng));
+    if rng.gen_range(0, 100) < CRITICAL_CHANCE {
+        let damage = reduce_damage(damage * CRITICAL_MULTIPLIER, defense.armor);
+        AttackResult::Critical { damage }
+    } else {
+        AttackResult::Hit { damage: reduce_damage(damage, defense.armor) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::GameRng;
+
+    #[test]
+    fn parse_dice() {
+        assert_eq!(Dice::parse("1d6"), Some(Dice { count: 1, sides: 6, bonus: 0 }));
+        assert_eq!(Dice::parse("2d4+1"), Some(Dice { count: 2, sides: 4, bonus: 1 }));
+        assert_eq!(Dice::parse("1d8-1"), Some(Dice { count: 1, sides: 8, bonus: -1 }));
+        for text in &["2d4+1", "1d8-1", "3d6"] {
+            assert_eq!(Dice::parse(text).unwrap().to_string(), *text);
+        }
+    }
+
+    #[test]
+    fn parse_malformed_dice() {
+        for text in &["", "d6", "1d", "6", "0d6", "1d0", "-1d6", "1d6+", "1d6+x", "ad6",
+                      "1d6 ", "1d+1", "1d6+1+1", "1x6", "1d6+-1", "1d6++1", "1d6--1",
+                      "+1d6", "1d+6"] {
+            assert_eq!(Dice::parse(text), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn dice_rolls_stay_in_range() {
+        let dice = Dice::parse("2d4+1").unwrap();
+        let mut rng = GameRng::new(7);
+        for _ in 0..1000 {
+            let roll = dice.roll(&mut rng);
+            assert!((3..=9).contains(&roll), "{}", roll);
+        }
+    }
+
+    #[test]
+    fn hit_chance_is_clamped() {
+        assert_eq!(hit_chance(0, 0), BASE_HIT_CHANCE);
+        assert_eq!(hit_chance(2, 0), BASE_HIT_CHANCE + 2 * HIT_CHANCE_PER_POINT);
+        assert_eq!(hit_chance(0, 2), BASE_HIT_CHANCE - 2 * HIT_CHANCE_PER_POINT);
+        assert_eq!(hit_chance(100, 0), MAX_HIT_CHANCE);
+        assert_eq!(hit_chance(0, 100), MIN_HIT_CHANCE);
+    }
+
+    #[test]
+    fn armor_never_blocks_a_hit_entirely() {
+        assert_eq!(reduce_damage(5, 2), 3);
+        assert_eq!(reduce_damage(5, 10), 1);
+        // negative armor adds nothing
+        assert_eq!(reduce_damage(5, -3), 5);
+    }
+
+    #[test]
+    fn resistances_and_vulnerabilities() {
+        assert_eq!(resist_damage(10, 0), 10);
+        assert_eq!(resist_damage(10, 50), 5);
+        // rounded to the nearest
+        assert_eq!(resist_damage(5, 50), 3);
+        assert_eq!(resist_damage(10, 100), 0);
+        assert_eq!(resist_damage(10, 150), 0);
+        assert_eq!(resist_damage(10, -50), 15);
+        assert_eq!(resist_damage(0, -50), 0);
+
+        let resistances = Resistances { fire: 50, cold: -50, ..Resistances::default() }
+            .combine(Resistances { fire: 50, ..Resistances::default() });
+        assert_eq!(resist_damage(10, resistances.get(DamageType::Fire)), 0);
+        assert_eq!(resist_damage(10, resistances.get(DamageType::Cold)), 15);
+        assert_eq!(resist_damage(10, resistances.get(DamageType::Physical)), 10);
+    }
+
+    #[test]
+    fn seeded_attacks_are_repeatable() {
+        let attack = Attack { accuracy: 0, power: 2, dice: Dice::parse("1d6") };
+        let defense = Defense { evasion: 0, armor: 1 };
+        let fight = |seed| {
+            let mut rng = GameRng::new(seed);
+            (0..100).map(|_| roll_attack(attack, defense, &mut rng)).collect::<Vec<_>>()
+        };
+        let results = fight(42);
+        assert_eq!(results, fight(42));
+        assert_ne!(results, fight(43));
+
+        for result in results {
+            match result {
+                AttackResult::Miss => {}
+                AttackResult::Hit { damage } => assert!((2..=7).contains(&damage)),
+                AttackResult::Critical { damage } => assert!((5..=15).contains(&damage)),
+            }
+        }
+    }
+
+    #[test]
+    fn hits_follow_the_hit_chance() {
+        let mut rng = GameRng::new(1);
+        let defense = Defense { evasion: 0, armor: 0 };
+        let hits = |accuracy, rng: &mut GameRng| {
+            let attack = Attack { accuracy, power: 1, dice: None };
+            (0..1000).filter(|_| roll_attack(attack, defense, rng) != AttackResult::Miss)
+                .count()
+        };
+        // 95% and 5%, give or take
+        assert!(hits(100, &mut rng) > 900);
+        assert!(hits(-100, &mut rng) < 100);
+    }
+}
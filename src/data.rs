@@ -0,0 +1,321 @@
+//! Game data read from JSON files at startup. Every loader goes through
+//! `Fields`, which checks each value and names the offending field when a
+//! file is wrong.
+
+use std::fs::File;
+use std::io::Read;
+use std::error::Error;
+
+use serde_json::{Map as JsonMap, Value};
+
+use crate::colors::Color;
+use crate::combat::{DamageType, Dice, Resistances};
+use crate::items::{parse_items, ItemTable};
+use crate::map::Transition;
+use crate::mapgen::{parse_dungeon, DungeonTable};
+use crate::monsters::{parse_monsters, MonsterTable};
+use crate::vaults::{check_vaults, parse_vaults, VaultTable};
+
+pub const MONSTERS_FILE: &str = "monsters.json";
+pub const ITEMS_FILE: &str = "items.json";
+pub const DUNGEON_FILE: &str = "dungeon.json";
+pub const VAULTS_FILE: &str = "vaults.json";
+
+/// Everything loaded from the data files
+#[derive(Clone, Debug, Default)]
+pub struct Catalog {
+    pub monsters: MonsterTable,
+    pub items: ItemTable,
+    pub dungeon: DungeonTable,
+    pub vaults: VaultTable,
+}
+
+impl Catalog {
+    /// load the data files from the current directory
+    pub fn load() -> Result<Catalog, Box<dyn Error>> {
+        let catalog = Catalog {
+            monsters: load_file(MONSTERS_FILE, parse_monsters)?,
+            items: load_file(ITEMS_FILE, parse_items)?,
+            dungeon: load_file(DUNGEON_FILE, parse_dungeon)?,
+            vaults: load_file(VAULTS_FILE, parse_vaults)?,
+        };
+        check_vaults(&catalog).map_err(|e| format!("{}: {}", VAULTS_FILE, e))?;
+        Ok(catalog)
+    }
+}
+
+fn load_file<T>(path: &str, parse: fn(&str) -> Result<T, String>)
+                -> Result<T, Box<dyn Error>> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|e| format!("{}: {}", path, e))?;
+    parse(&text).map_err(|e| format!("{}: {}", path, e).into())
+}
+
+/// The fields of a JSON object, with errors that say where things went wrong
+pub struct Fields<'a> {
+    pub context: String,
+    object: &'a JsonMap<String, Value>,
+}
+
+impl<'a> Fields<'a> {
+    /// `known` lists every field allowed in the object, to catch typos
+    pub fn new(context: &str, value: &'a Value, known: &[&str]) -> Result<Self, String> {
+        let object = value.as_object()
+            .ok_or_else(|| format!("{}: expected an object", context))?;
+        if let Some(field) = object.keys().find(|key| !known.contains(&key.as_str())) {
+            return Err(format!("{}: unknown field `{}`", context, field));
+        }
+        Ok(Fields { context: context.into(), object })
+    }
+
+    /// the same fields, described differently in errors
+    pub fn with_context(self, context: &str) -> Self {
+        Fields { context: context.into(), object: self.object }
+    }
+
+    fn error<T>(&self, field: &str, message: &str) -> Result<T, String> {
+        Err(format!("{}: field `{}` {}", self.context, field, message))
+    }
+
+    pub fn has(&self, field: &str) -> bool {
+        self.object.contains_key(field)
+    }
+
+    pub fn get(&self, field: &str) -> Result<&'a Value, String> {
+        match self.object.get(field) {
+            Some(value) => Ok(value),
+            None => self.error(field, "is missing"),
+        }
+    }
+
+    pub fn string(&self, field: &str) -> Result<String, String> {
+        match self.get(field)?.as_str() {
+            Some(text) => Ok(text.into()),
+            None => self.error(field, "must be a string"),
+        }
+    }
+
+    /// an integer that is at least `min`
+    pub fn int(&self, field: &str, min: i32) -> Result<i32, String> {
+        self.int_between(field, min, i32::MAX)
+    }
+
+    /// an integer from `min` to `max`
+    pub fn int_between(&self, field: &str, min: i32, max: i32) -> Result<i32, String> {
+        match self.get(field)?.as_i64() {
+            Some(number) if number >= min as i64 && number <= max as i64 => Ok(number as i32),
+            Some(_) if max == i32::MAX => self.error(field, &format!("must be at least {}", min)),
+            Some(_) => self.error(field, &format!("must be from {} to {}", min, max)),
+            None => self.error(field, "must be an integer"),
+        }
+    }
+
+    pub fn optional_int(&self, field: &str, min: i32) -> Result<Option<i32>, String> {
+        self.optional_int_between(field, min, i32::MAX)
+    }
+
+    pub fn optional_int_between(&self, field: &str, min: i32, max: i32)
+                                -> Result<Option<i32>, String> {
+        if self.has(field) {
+            self.int_between(field, min, max).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn bool(&self, field: &str) -> Result<bool, String> {
+        match self.get(field)?.as_bool() {
+            Some(value) => Ok(value),
+            None => self.error(field, "must be true or false"),
+        }
+    }
+
+    pub fn array(&self, field: &str) -> Result<&'a Vec<Value>, String> {
+        match self.get(field)?.as_array() {
+            Some(array) => Ok(array),
+            None => self.error(field, "must be a list"),
+        }
+    }
+
+    pub fn glyph(&self, field: &str) -> Result<char, String> {
+        let text = self.string(field)?;
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(glyph), None) => Ok(glyph),
+            _ => self.error(field, "must be a single character"),
+        }
+    }
+
+    /// dice written as `"2d4+1"`, or None if the field isn't there
+    pub fn optional_dice(&self, field: &str) -> Result<Option<Dice>, String> {
+        if !self.has(field) {
+            return Ok(None);
+        }
+        match Dice::parse(&self.string(field)?) {
+            Some(dice) => Ok(Some(dice)),
+            None => self.error(field, "must be dice such as \"1d6\" or \"2d4+1\""),
+        }
+    }
+
+    /// one of "physical", "fire", "lightning", "cold" or "poison"
+    pub fn damage_type(&self, field: &str) -> Result<DamageType, String> {
+        match DamageType::parse(&self.string(field)?) {
+            Some(kind) => Ok(kind),
+            None => self.error(field, "must be \"physical\", \"fire\", \"lightning\", \
+                                       \"cold\" or \"poison\""),
+        }
+    }
+
+    /// resistances in percent by damage type, such as `{"fire": 50}`, all 0
+    /// if the field isn't there
+    pub fn resistances(&self, field: &str) -> Result<Resistances, String> {
+        if !self.has(field) {
+            return Ok(Resistances::default());
+        }
+        let context = format!("{}: field `{}`", self.context, field);
+        let fields = Fields::new(&context, self.get(field)?,
+                                 &["physical", "fire", "lightning", "cold", "poison"])?;
+        let resistance = |field| fields.optional_int(field, -100)
+            .map(|resistance| resistance.unwrap_or(0));
+        Ok(Resistances {
+            physical: resistance("physical")?,
+            fire: resistance("fire")?,
+            lightning: resistance("lightning")?,
+            cold: resistance("cold")?,
+            poison: resistance("poison")?,
+        })
+    }
+
+    /// a color written as `[r, g, b]`
+    pub fn color(&self, field: &str) -> Result<Color, String> {
+        let parts: Option<Vec<u8>> = self.get(field)?.as_array().and_then(|parts| {
+            parts.iter()
+                 .map(|part| part.as_u64().filter(|part| *part <= 255).map(|part| part as u8))
+                 .collect()
+        });
+        match parts {
+            Some(ref parts) if parts.len() == 3 => {
+                Ok(Color::new(parts[0], parts[1], parts[2]))
+            }
+            _ => self.error(field, "must be a list of three numbers from 0 to 255"),
+        }
+    }
+
+    /// a `from_dungeon_level` table: a list of `{"level": l, "value": v}`
+    /// with increasing levels
+    pub fn transitions(&self, field: &str) -> Result<Vec<Transition>, String> {
+        let mut table: Vec<Transition> = vec![];
+        for (index, value) in self.array(field)?.iter().enumerate() {
+            let context = format!("{}: field `{}`, entry {}", self.context, field, index + 1);
+            let entry = Fields::new(&context, value, &["level", "value"])?;
+            let transition = Transition {
+                level: entry.int("level", 1)? as u32,
+                value: entry.int("value", 0)? as u32,
+            };
+            if table.last().is_some_and(|last| last.level >= transition.level) {
+                return Err(format!("{}: levels must be increasing", context));
+            }
+            table.push(transition);
+        }
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::items::{parse_items, ItemTable};
+    use crate::monsters::{parse_monsters, MonsterTable};
+
+    /// load a file with a single orc, changed by `change`
+    fn load_orc<F: FnOnce(&mut Value)>(change: F) -> Result<MonsterTable, String> {
+        let mut orc = json!({
+            "name": "orc",
+            "glyph": "o",
+            "color": [63, 127, 63],
+            "fighter": {"hp": 4, "power": 1, "defense": 0, "xp": 2, "damage": "1d3"},
+            "ai": "basic",
+            "asleep": 50,
+            "spawn_weight": [{"level": 1, "value": 16}]
+        });
+        change(&mut orc);
+        let file = json!({"max_monsters": [{"level": 1, "value": 2}], "monsters": [orc]});
+        parse_monsters(&file.to_string())
+    }
+
+    /// load a file with a single potion, changed by `change`
+    fn load_potion<F: FnOnce(&mut Value)>(change: F) -> Result<ItemTable, String> {
+        let mut potion = json!({
+            "name": "Healing Potion",
+            "glyph": "!",
+            "color": [127, 0, 255],
+            "effect": {"type": "heal", "amount": 6},
+            "spawn_weight": [{"level": 1, "value": 6}]
+        });
+        change(&mut potion);
+        let file = json!({"max_items": [{"level": 1, "value": 1}], "items": [potion]});
+        parse_items(&file.to_string())
+    }
+
+    fn monster_error<F: FnOnce(&mut Value)>(change: F) -> String {
+        load_orc(change).unwrap_err()
+    }
+
+    fn item_error<F: FnOnce(&mut Value)>(change: F) -> String {
+        load_potion(change).unwrap_err()
+    }
+
+    #[test]
+    fn unchanged_files_load() {
+        assert!(load_orc(|_| {}).is_ok());
+        assert!(load_potion(|_| {}).is_ok());
+    }
+
+    #[test]
+    fn missing_field() {
+        assert_eq!(monster_error(|orc| { orc["fighter"].as_object_mut().unwrap().remove("hp"); }),
+                   "monster 1 (orc): field `fighter`: field `hp` is missing");
+        assert_eq!(monster_error(|orc| { orc.as_object_mut().unwrap().remove("ai"); }),
+                   "monster 1 (orc): field `ai` is missing");
+    }
+
+    #[test]
+    fn wrong_type() {
+        assert_eq!(monster_error(|orc| orc["fighter"]["hp"] = json!("four")),
+                   "monster 1 (orc): field `fighter`: field `hp` must be an integer");
+        assert_eq!(monster_error(|orc| orc["glyph"] = json!(7)),
+                   "monster 1 (orc): field `glyph` must be a string");
+        assert_eq!(item_error(|potion| potion["effect"]["amount"] = json!([6])),
+                   "item 1 (Healing Potion): field `effect`: field `amount` must be an integer");
+    }
+
+    #[test]
+    fn unknown_names() {
+        assert_eq!(monster_error(|orc| orc["ai"] = json!("smart")),
+                   "monster 1 (orc): field `ai`: unknown AI \"smart\", expected \"basic\" \
+                    or \"ranged\"");
+        assert_eq!(item_error(|potion| potion["effect"]["type"] = json!("teleport")),
+                   "item 1 (Healing Potion): field `effect`: field `type`: unknown effect \
+                    \"teleport\", expected \"heal\", \"bolt\", \"area\" or \"status\"");
+    }
+
+    #[test]
+    fn values_out_of_range() {
+        assert_eq!(monster_error(|orc| orc["asleep"] = json!(101)),
+                   "monster 1 (orc): field `asleep` must be from 0 to 100");
+        assert_eq!(monster_error(|orc| orc["fighter"]["hp"] = json!(0)),
+                   "monster 1 (orc): field `fighter`: field `hp` must be at least 1");
+    }
+
+    #[test]
+    fn malformed_dice() {
+        for dice in &["1d6+-1", "1d6++1"] {
+            assert_eq!(monster_error(|orc| orc["fighter"]["damage"] = json!(dice)),
+                       "monster 1 (orc): field `fighter`: field `damage` must be dice such as \
+                        \"1d6\" or \"2d4+1\"");
+        }
+    }
+}
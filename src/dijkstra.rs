@@ -0,0 +1,388 @@
+//! Dijkstra maps: the walking distance from every tile of the map to the
+//! closest of some goals. One map serves everyone heading for the same
+//! goals: walking downhill gets closer, walking to the lowest tile of the
+//! fleeing map gets away, and keeping to a value keeps a distance.
+//! Only the map is taken into account, with the same costs as in `path`.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::map::{Map, STAIRS_DOWN};
+use crate::object::{Movement, Object};
+use crate::path::{tile_cost, travel_cost, TileCost, DIAGONAL_COST, DIRECTIONS, STRAIGHT_COST};
+
+const UNREACHABLE: i32 = i32::MAX;
+
+/// how much farther than the goals a fleeing creature looks for safety. Above
+/// 1 so that cornered creatures prefer to run past the danger to open space
+const FLEE_FACTOR: (i32, i32) = (6, 5);
+
+#[derive(Clone, Debug)]
+pub struct DijkstraMap {
+    movement: Movement,
+    cost: TileCost,
+    width: i32,
+    height: i32,
+    values: Vec<i32>,
+}
+
+impl DijkstraMap {
+    /// distance to the closest of `goals` for creatures moving that way, in
+    /// the same units as `path`
+    pub fn new(map: &Map, goals: &[(i32, i32)], movement: Movement) -> Self {
+        DijkstraMap::with_costs(tile_cost, map, goals, movement)
+    }
+
+    /// `new` with other costs for the tiles, such as `travel_cost`
+    pub fn with_costs(cost: TileCost, map: &Map, goals: &[(i32, i32)], movement: Movement)
+                      -> Self {
+        let width = map.len() as i32;
+        let height = map.first().map_or(0, |column| column.len()) as i32;
+        let mut dijkstra = DijkstraMap {
+            movement,
+            cost,
+            width,
+            height,
+            values: vec![UNREACHABLE; (width * height) as usize],
+        };
+        for &(x, y) in goals {
+            if dijkstra.inside(x, y) && cost(&map[x as usize][y as usize], movement).is_some() {
+                let index = dijkstra.index(x, y);
+                dijkstra.values[index] = 0;
+            }
+        }
+        dijkstra.scan(map);
+        dijkstra
+    }
+
+    /// A map to get away from the goals of this one. Walking downhill on it
+    /// goes away from the goals, and around them when it's the only way out.
+    pub fn fleeing(&self, map: &Map) -> Self {
+        let mut fleeing = self.clone();
+        for value in fleeing.values.iter_mut() {
+            if *value != UNREACHABLE {
+                *value = -*value * FLEE_FACTOR.0 / FLEE_FACTOR.1;
+            }
+        }
+        fleeing.scan(map);
+        fleeing
+    }
+
+    /// the value of a tile, None for walls and tiles no goal can be reached from
+    pub fn value(&self, x: i32, y: i32) -> Option<i32> {
+        if !self.inside(x, y) {
+            return None;
+        }
+        match self.values[self.index(x, y)] {
+            UNREACHABLE => None,
+            value => Some(value),
+        }
+    }
+
+    /// the neighbour with the lowest value, if it is lower than here
+    pub fn downhill(&self, x: i32, y: i32) -> Option<(i32, i32)> {
+        let here = self.value(x, y)?;
+        self.best_neighbour(x, y, |value| value)
+            .filter(|&(nx, ny)| self.value(nx, ny).is_some_and(|value| value < here))
+    }
+
+    /// The neighbour whose value is the closest to `target`, if it is closer
+    /// than here. Walking it keeps at `target` from the goals, such as an
+    /// archer staying at range.
+    pub fn towards_value(&self, x: i32, y: i32, target: i32) -> Option<(i32, i32)> {
+        let here = (self.value(x, y)? - target).abs();
+        self.best_neighbour(x, y, |value| (value - target).abs())
+            .filter(|&(nx, ny)| {
+                self.value(nx, ny).is_some_and(|value| (value - target).abs() < here)
+            })
+    }
+
+    /// the reachable neighbour with the lowest score, the first one on ties
+    fn best_neighbour<F>(&self, x: i32, y: i32, score: F) -> Option<(i32, i32)>
+        where F: Fn(i32) -> i32
+    {
+        let mut best: Option<((i32, i32), i32)> = None;
+        for &(dx, dy) in DIRECTIONS.iter() {
+            if let Some(value) = self.value(x + dx, y + dy) {
+                let score = score(value);
+                if best.is_none_or(|(_, best_score)| score < best_score) {
+                    best = Some(((x + dx, y + dy), score));
+                }
+            }
+        }
+        best.map(|(tile, _)| tile)
+    }
+
+    fn inside(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.width && y < self.height
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        (x * self.height + y) as usize
+    }
+
+    /// spread the values out from every tile that has one, lowering each
+    /// tile to its cheapest neighbour plus the step
+    fn scan(&mut self, map: &Map) {
+        let mut open: BinaryHeap<Reverse<(i32, usize)>> = self.values.iter()
+            .enumerate()
+            .filter(|&(_, &value)| value != UNREACHABLE)
+            .map(|(index, &value)| Reverse((value, index)))
+            .collect();
+        while let Some(Reverse((value, index))) = open.pop() {
+            if value > self.values[index] {
+                continue;   // already lowered since it was queued
+            }
+            let (x, y) = (index as i32 / self.height, index as i32 % self.height);
+            for &(dx, dy) in DIRECTIONS.iter() {
+                let (nx, ny) = (x + dx, y + dy);
+                if !self.inside(nx, ny) {
+                    continue;
+                }
+                let tile_cost = match (self.cost)(&map[nx as usize][ny as usize], self.movement) {
+                    Some(tile_cost) => tile_cost,
+                    None => continue,
+                };
+                let next = self.index(nx, ny);
+                let cost = value + tile_cost +
+                    if dx != 0 && dy != 0 { DIAGONAL_COST } else { STRAIGHT_COST };
+                if cost < self.values[next] {
+                    self.values[next] = cost;
+                    open.push(Reverse((cost, next)));
+                }
+            }
+        }
+    }
+}
+
+impl Default for DijkstraMap {
+    /// a map of nothing, where no goal can be reached
+    fn default() -> Self {
+        DijkstraMap {
+            movement: Movement::Walk,
+            cost: tile_cost,
+            width: 0,
+            height: 0,
+            values: vec![],
+        }
+    }
+}
+
+/// The maps the monsters share while the player waits for their next turn:
+/// the distance to the player and the way away from them, for each way of
+/// moving. Built once the player has acted.
+#[derive(Clone, Debug, Default)]
+pub struct ChaseMaps {
+    to_player: [DijkstraMap; 3],
+    from_player: [DijkstraMap; 3],
+}
+
+impl ChaseMaps {
+    pub fn new(map: &Map, player: (i32, i32)) -> Self {
+        let to_player = [Movement::Walk, Movement::Swim, Movement::Fly]
+            .map(|movement| DijkstraMap::new(map, &[player], movement));
+        let from_player = to_player.each_ref().map(|to_player| to_player.fleeing(map));
+        ChaseMaps { to_player, from_player }
+    }
+
+    pub fn to_player(&self, movement: Movement) -> &DijkstraMap {
+        &self.to_player[movement_index(movement)]
+    }
+
+    /// walking downhill on it gets away from the player
+    pub fn from_player(&self, movement: Movement) -> &DijkstraMap {
+        &self.from_player[movement_index(movement)]
+    }
+}
+
+fn movement_index(movement: Movement) -> usize {
+    match movement {
+        Movement::Walk => 0,
+        Movement::Swim => 1,
+        Movement::Fly => 2,
+    }
+}
+
+/// The distance to what the player may travel to on their own, for the way
+/// they move and around what would hurt them. Built when their turn comes.
+#[derive(Clone, Debug, Default)]
+pub struct GoalMaps {
+    /// the tiles the player hasn't seen yet
+    pub unexplored: DijkstraMap,
+    /// the items lying on tiles the player has seen
+    pub items: DijkstraMap,
+    pub stairs_down: DijkstraMap,
+}
+
+impl GoalMaps {
+    pub fn new(map: &Map, objects: &[Object], movement: Movement) -> Self {
+        let mut unexplored = vec![];
+        for (x, column) in map.iter().enumerate() {
+            for (y, tile) in column.iter().enumerate() {
+                if !tile.explored && tile.passable() {
+                    unexplored.push((x as i32, y as i32));
+                }
+            }
+        }
+        let items: Vec<_> = objects.iter()
+            .filter(|object| {
+                object.item.is_some() && map[object.x as usize][object.y as usize].explored
+            })
+            .map(|object| object.pos())
+            .collect();
+        let stairs: Vec<_> = objects.iter()
+            .filter(|object| object.name == STAIRS_DOWN)
+            .map(|object| object.pos())
+            .collect();
+        GoalMaps {
+            unexplored: DijkstraMap::with_costs(travel_cost, map, &unexplored, movement),
+            items: DijkstraMap::with_costs(travel_cost, map, &items, movement),
+            stairs_down: DijkstraMap::with_costs(travel_cost, map, &stairs, movement),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{Terrain, Tile, Trap, TrapKind};
+
+    /// a map drawn as rows of text: `#` is a wall, `:` a chasm, `^` a hidden
+    /// trap and anything else floor
+    fn map_from(rows: &[&str]) -> Map {
+        let width = rows[0].len();
+        (0..width)
+            .map(|x| {
+                rows.iter()
+                    .map(|row| match row.as_bytes()[x] {
+                        b'#' => Tile::wall(),
+                        b':' => {
+                            let mut tile = Tile::empty();
+                            tile.terrain = Terrain::Chasm;
+                            tile
+                        }
+                        b'^' => {
+                            let mut tile = Tile::empty();
+                            tile.trap = Some(Trap { kind: TrapKind::Dart, revealed: false });
+                            tile
+                        }
+                        _ => Tile::empty(),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn corridor() -> Map {
+        map_from(&["########",
+                   "#......#",
+                   "########"])
+    }
+
+    #[test]
+    fn distances() {
+        let dijkstra = DijkstraMap::new(&corridor(), &[(1, 1)], Movement::Walk);
+        assert_eq!(dijkstra.value(1, 1), Some(0));
+        assert_eq!(dijkstra.value(4, 1), Some(3 * STRAIGHT_COST));
+        assert_eq!(dijkstra.value(0, 1), None);
+        assert_eq!(dijkstra.value(-1, 1), None);
+
+        let open = map_from(&["...",
+                              "...",
+                              "..."]);
+        let dijkstra = DijkstraMap::new(&open, &[(0, 0)], Movement::Walk);
+        assert_eq!(dijkstra.value(2, 2), Some(2 * DIAGONAL_COST));
+        assert_eq!(dijkstra.value(2, 1), Some(DIAGONAL_COST + STRAIGHT_COST));
+    }
+
+    #[test]
+    fn closest_goal() {
+        let dijkstra = DijkstraMap::new(&corridor(), &[(1, 1), (6, 1)], Movement::Walk);
+        assert_eq!(dijkstra.value(5, 1), Some(STRAIGHT_COST));
+        assert_eq!(dijkstra.value(3, 1), Some(2 * STRAIGHT_COST));
+    }
+
+    #[test]
+    fn unreachable() {
+        let map = map_from(&[".#.",
+                             ".#.",
+                             ".#."]);
+        let dijkstra = DijkstraMap::new(&map, &[(0, 0)], Movement::Walk);
+        assert_eq!(dijkstra.value(0, 2), Some(2 * STRAIGHT_COST));
+        assert_eq!(dijkstra.value(2, 0), None);
+        assert_eq!(dijkstra.downhill(2, 0), None);
+        assert_eq!(dijkstra.fleeing(&map).downhill(2, 0), None);
+        assert_eq!(dijkstra.towards_value(2, 0, STRAIGHT_COST), None);
+
+        // a goal in a wall reaches nothing
+        let dijkstra = DijkstraMap::new(&map, &[(1, 1)], Movement::Walk);
+        assert_eq!(dijkstra.value(0, 0), None);
+    }
+
+    #[test]
+    fn only_fliers_cross_chasms() {
+        let map = map_from(&[".:.",
+                             ".:.",
+                             ".:."]);
+        let walking = DijkstraMap::new(&map, &[(0, 0)], Movement::Walk);
+        assert_eq!(walking.value(2, 0), None);
+        let flying = DijkstraMap::new(&map, &[(0, 0)], Movement::Fly);
+        assert_eq!(flying.value(2, 0), Some(2 * STRAIGHT_COST));
+        assert_eq!(flying.downhill(2, 0), Some((1, 0)));
+    }
+
+    #[test]
+    fn downhill_goes_around_walls() {
+        let map = map_from(&[".#.",
+                             ".#.",
+                             "..."]);
+        let dijkstra = DijkstraMap::new(&map, &[(0, 0)], Movement::Walk);
+        assert_eq!(dijkstra.downhill(2, 0), Some((2, 1)));
+        assert_eq!(dijkstra.downhill(2, 1), Some((1, 2)));
+        assert_eq!(dijkstra.downhill(1, 2), Some((0, 1)));
+        assert_eq!(dijkstra.downhill(0, 1), Some((0, 0)));
+        // there already
+        assert_eq!(dijkstra.downhill(0, 0), None);
+    }
+
+    #[test]
+    fn fleeing_goes_away() {
+        let map = corridor();
+        let fleeing = DijkstraMap::new(&map, &[(1, 1)], Movement::Walk).fleeing(&map);
+        assert_eq!(fleeing.downhill(3, 1), Some((4, 1)));
+        assert_eq!(fleeing.downhill(5, 1), Some((6, 1)));
+        // nowhere farther to go
+        assert_eq!(fleeing.downhill(6, 1), None);
+        assert_eq!(fleeing.value(0, 1), None);
+    }
+
+    #[test]
+    fn towards_value_keeps_the_distance() {
+        let dijkstra = DijkstraMap::new(&corridor(), &[(1, 1)], Movement::Walk);
+        let wanted = 3 * STRAIGHT_COST;
+        assert_eq!(dijkstra.towards_value(2, 1, wanted), Some((3, 1)));
+        assert_eq!(dijkstra.towards_value(6, 1, wanted), Some((5, 1)));
+        // at the right distance already
+        assert_eq!(dijkstra.towards_value(4, 1, wanted), None);
+    }
+
+    #[test]
+    fn chase_maps_follow_the_way_of_moving() {
+        let chase = ChaseMaps::new(&map_from(&[".:."]), (0, 0));
+        assert_eq!(chase.to_player(Movement::Walk).value(2, 0), None);
+        assert_eq!(chase.to_player(Movement::Fly).downhill(2, 0), Some((1, 0)));
+        assert_eq!(chase.from_player(Movement::Fly).downhill(1, 0), Some((2, 0)));
+    }
+
+    #[test]
+    fn only_monsters_know_hidden_traps() {
+        let map = map_from(&["...",
+                             ".^.",
+                             "..."]);
+        // around the trap for monsters, straight over it for the player
+        let monsters = DijkstraMap::new(&map, &[(0, 1)], Movement::Walk);
+        assert_eq!(monsters.value(2, 1), Some(2 * DIAGONAL_COST));
+        let player = DijkstraMap::with_costs(travel_cost, &map, &[(0, 1)], Movement::Walk);
+        assert_eq!(player.value(2, 1), Some(2 * STRAIGHT_COST));
+    }
+}
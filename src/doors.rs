@@ -0,0 +1,69 @@
+//! Doors between rooms and corridors. Walking into a closed door opens it,
+//! which takes the move, and locked doors only open for the player, using up
+//! a key. The FOV map follows every change right away, and the monsters
+//! around hear it.
+
+use crate::PLAYER;
+use crate::colors;
+use crate::game::{Game, MessageLog};
+use crate::item::Item;
+use crate::map::Door;
+use crate::noise::{make_noise, DOOR_NOISE};
+use crate::object::Object;
+
+/// Open the door on the tile for the object walking into it, if there is a
+/// closed one. Returns whether a door was in the way, in which case the move
+/// went into it.
+pub fn open_door(id: usize, x: i32, y: i32, game: &mut Game) -> bool {
+    match game.map[x as usize][y as usize].door {
+        Some(Door::Closed) => {
+            set_door(x, y, Door::Open, game);
+            make_noise(x, y, DOOR_NOISE, game);
+            true
+        }
+        Some(Door::Locked) => {
+            // monsters have no keys
+            if id == PLAYER {
+                unlock_door(x, y, game);
+            }
+            true
+        }
+        Some(Door::Open) | None => false,
+    }
+}
+
+fn unlock_door(x: i32, y: i32, game: &mut Game) {
+    let key = game.inventory.iter().position(|item| item.item == Some(Item::Key));
+    match key {
+        Some(key) => {
+            let key = game.inventory.remove(key);
+            game.log.add(format!("You unlock the door with the {}.", key.name),
+                         colors::LIGHT_CYAN);
+            set_door(x, y, Door::Open, game);
+            make_noise(x, y, DOOR_NOISE, game);
+        }
+        None => game.log.add("The door is locked.", colors::LIGHT_GREY),
+    }
+}
+
+/// Close the open door on the tile. Returns false if there is none, or if
+/// something is in the way.
+pub fn close_door(x: i32, y: i32, objects: &[Object], game: &mut Game) -> bool {
+    if game.map[x as usize][y as usize].door != Some(Door::Open) {
+        game.log.add("There is no open door there.", colors::LIGHT_GREY);
+        return false;
+    }
+    if let Some(object) = objects.iter().find(|object| object.pos() == (x, y)) {
+        game.log.add(format!("The {} is in the way.", object.name), colors::LIGHT_GREY);
+        return false;
+    }
+    set_door(x, y, Door::Closed, game);
+    make_noise(x, y, DOOR_NOISE, game);
+    true
+}
+
+pub fn set_door(x: i32, y: i32, door: Door, game: &mut Game) {
+    let tile = &mut game.map[x as usize][y as usize];
+    tile.set_door(door);
+    game.fov.set(x, y, !tile.block_sight, !tile.blocked);
+}
@@ -0,0 +1,129 @@
+//! Field of view. A small ray casting implementation with the same interface
+//! as the libtcod map, so the game logic can run without a window.
+
+#[derive(Clone, Debug)]
+pub struct FovMap {
+    width: i32,
+    height: i32,
+    transparent: Vec<bool>,
+    walkable: Vec<bool>,
+    in_fov: Vec<bool>,
+}
+
+impl Default for FovMap {
+    fn default() -> Self {
+        FovMap::new(0, 0)
+    }
+}
+
+impl FovMap {
+    pub fn new(width: i32, height: i32) -> Self {
+        let size = (width * height) as usize;
+        FovMap {
+            width,
+            height,
+            transparent: vec![false; size],
+            walkable: vec![false; size],
+            in_fov: vec![false; size],
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x >= 0 && y >= 0 && x < self.width && y < self.height {
+            Some((x + y * self.width) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, transparent: bool, walkable: bool) {
+        if let Some(index) = self.index(x, y) {
+            self.transparent[index] = transparent;
+            self.walkable[index] = walkable;
+        }
+    }
+
+    pub fn is_transparent(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).is_some_and(|index| self.transparent[index])
+    }
+
+    pub fn is_walkable(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).is_some_and(|index| self.walkable[index])
+    }
+
+    pub fn is_in_fov(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).is_some_and(|index| self.in_fov[index])
+    }
+
+    /// cast a ray from the origin to every tile on the edge of the view
+    /// square. A radius of 0 means no limit.
+    pub fn compute_fov(&mut self, x: i32, y: i32, radius: i32, light_walls: bool) {
+        for cell in self.in_fov.iter_mut() {
+            *cell = false;
+        }
+        let origin = match self.index(x, y) {
+            Some(index) => index,
+            None => return,
+        };
+        self.in_fov[origin] = true;
+
+        let (min_x, max_x, min_y, max_y) = if radius > 0 {
+            (x - radius, x + radius, y - radius, y + radius)
+        } else {
+            (0, self.width - 1, 0, self.height - 1)
+        };
+        for edge_x in min_x..(max_x + 1) {
+            self.cast_ray(x, y, edge_x, min_y, radius, light_walls);
+            self.cast_ray(x, y, edge_x, max_y, radius, light_walls);
+        }
+        for edge_y in (min_y + 1)..max_y {
+            self.cast_ray(x, y, min_x, edge_y, radius, light_walls);
+            self.cast_ray(x, y, max_x, edge_y, radius, light_walls);
+        }
+    }
+
+    fn cast_ray(&mut self, x: i32, y: i32, to_x: i32, to_y: i32, radius: i32,
+                light_walls: bool) {
+        for (cx, cy) in line((x, y), (to_x, to_y)) {
+            let index = match self.index(cx, cy) {
+                Some(index) => index,
+                None => return,
+            };
+            if radius > 0 && (cx - x).pow(2) + (cy - y).pow(2) > radius.pow(2) {
+                return;
+            }
+            if self.transparent[index] {
+                self.in_fov[index] = true;
+            } else {
+                if light_walls {
+                    self.in_fov[index] = true;
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// Bresenham line from `start` to `end`, not including the start tile
+pub fn line(start: (i32, i32), end: (i32, i32)) -> Vec<(i32, i32)> {
+    let (mut x, mut y) = start;
+    let dx = (end.0 - x).abs();
+    let dy = -(end.1 - y).abs();
+    let step_x = if x < end.0 { 1 } else { -1 };
+    let step_y = if y < end.1 { 1 } else { -1 };
+    let mut error = dx + dy;
+    let mut points = vec![];
+    while (x, y) != end {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+        points.push((x, y));
+    }
+    points
+}
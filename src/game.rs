@@ -0,0 +1,516 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::fs::File;
+use std::error::Error;
+
+use rand::Rng;
+
+use crate::PLAYER;
+use crate::ai::{hunters_in_view, lose_sight};
+use crate::colors::{self, Color};
+use crate::combat::{DamageType, Dice, Resistances};
+use crate::data::Catalog;
+use crate::dijkstra::{ChaseMaps, GoalMaps};
+use crate::doors::close_door;
+use crate::fov::FovMap;
+use crate::item::{drop_item, pick_item_up, use_item, Item};
+use crate::map::{make_map, Map, MAP_HEIGHT, MAP_WIDTH, STAIRS_DOWN, STAIRS_UP};
+use crate::noise::{make_noise, Noise, RUN_NOISE};
+use crate::object::{move_by, mut_two, DeathCallback, Equipment, Fighter, Object, Slot};
+use crate::path::DIRECTIONS;
+use crate::rng::GameRng;
+use crate::scheduler::{run_until_player_turn, ActionKind, NORMAL_SPEED, TURN_ENERGY};
+use crate::status::{tick_statuses, StatusKind};
+use crate::terrain::drop_held_items;
+use crate::traps::{fell_in_pit, search};
+
+const FOV_LIGHT_WALLS: bool = true;
+const TORCH_RADIUS: i32 = 10;
+
+pub const LEVEL_UP_BASE: i32 = 10;
+pub const LEVEL_UP_FACTOR: i32 = 5;
+
+pub const SAVE_FILE: &str = "savegame";
+
+#[derive(Serialize, Deserialize)]
+pub struct Game {
+    pub map: Map,
+    pub log: Messages,
+    pub inventory: Vec<Object>,
+    pub dungeon_level: u32,
+    // the floors visited before, by dungeon level, as they were left
+    pub levels: BTreeMap<u32, Level>,
+    // the seed the game was started with, for bug reports
+    pub seed: u64,
+    pub rng: GameRng,
+    // every action played so far, so a replay can be written at any time
+    pub history: Vec<PlayerAction>,
+    // rebuilt from the map after loading
+    #[serde(skip)]
+    pub fov: FovMap,
+    // loaded from the data files, not saved
+    #[serde(skip)]
+    pub catalog: Catalog,
+    // the way to the player and away, shared by the monsters. Rebuilt every
+    // turn
+    #[serde(skip)]
+    pub chase: ChaseMaps,
+    // the way to what the player may travel to. Rebuilt every turn
+    #[serde(skip)]
+    pub goals: GoalMaps,
+    // made during the current action, see `noise`
+    #[serde(skip)]
+    pub noises: Vec<Noise>,
+}
+
+/// A floor of the dungeon the player is not on. Its objects don't include
+/// the player, and nothing moves until the player comes back.
+#[derive(Serialize, Deserialize)]
+pub struct Level {
+    pub map: Map,
+    pub objects: Vec<Object>,
+}
+
+pub type Messages = Vec<(String, Color)>;
+
+pub trait MessageLog {
+    fn add<T: Into<String>>(&mut self, message: T, color: Color);
+}
+
+impl MessageLog for Vec<(String, Color)> {
+    fn add<T: Into<String>>(&mut self, message: T, color: Color) {
+        self.push((message.into(), color))
+    }
+}
+
+/// Everything the player can do in the game. Choices that used to be asked
+/// in the middle of a turn (target tiles, level up stats) are part of the
+/// action, so the game can be driven without a window.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PlayerAction {
+    Move { dx: i32, dy: i32 },
+    /// move faster but loudly, see `noise`
+    Run { dx: i32, dy: i32 },
+    Wait,
+    PickUp,
+    UseItem { inventory_id: usize, target: Option<(i32, i32)> },
+    DropItem { inventory_id: usize },
+    /// close the door next to the player, in that direction
+    CloseDoor { dx: i32, dy: i32 },
+    /// look for hidden traps around
+    Search,
+    TakeStairs,
+    LevelUp(Stat),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TurnResult {
+    TookTurn,
+    DidntTakeTurn,
+}
+
+/// Stats that can be raised when levelling up
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Stat {
+    Vitality,
+    Attack,
+    Defense,
+}
+
+/// experience needed by the player to reach the next level
+pub fn level_up_xp(player: &Object) -> i32 {
+    LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR
+}
+
+/// see if the player's experience is enough to level up
+pub fn can_level_up(objects: &[Object]) -> bool {
+    let player = &objects[PLAYER];
+    player.fighter.as_ref().map_or(0, |f| f.xp) >= level_up_xp(player)
+}
+
+fn level_up(stat: Stat, objects: &mut [Object], game: &mut Game) {
+    if !can_level_up(objects) {
+        return;
+    }
+    let player = &mut objects[PLAYER];
+    let level_up_xp = level_up_xp(player);
+    // level up if it is
+    player.level += 1;
+    game.log.add(format!("You feel more powerful! You are now level {}!",
+                         player.level),
+                 colors::YELLOW);
+    let fighter = player.fighter.as_mut().unwrap();
+    fighter.xp -= level_up_xp;
+    match stat {
+        Stat::Vitality => {
+            fighter.base_max_hp += 5;
+            fighter.hp += 5;
+        }
+        Stat::Attack => {
+            fighter.base_power += 1;
+        }
+        Stat::Defense => {
+            fighter.base_defense += 1;
+        }
+    }
+}
+
+/// handle player movements and attacks
+fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game,
+                         objects: &mut [Object]) -> ActionKind {
+    // the player coordinates moving to/attacking
+    let x = objects[PLAYER].x + dx;
+    let y = objects[PLAYER].y + dy;
+
+    // try to find an attackable object there
+    let target_id = objects.iter().position(|object| {
+        object.fighter.is_some() && object.pos() == (x, y)
+    });
+
+    // attack if there is a target. Otherwise, move
+    match target_id {
+        Some(target_id) => {
+            let (player, target) = mut_two(PLAYER, target_id, objects);
+            player.attack(target, game);
+            ActionKind::Attack
+        }
+        None => {
+            move_by(PLAYER, dx, dy, game, objects)
+        }
+    }
+}
+
+/// Go up or down the stairs the player is standing on, if any
+fn take_stairs(objects: &mut Vec<Object>, game: &mut Game) {
+    let stairs = objects.iter()
+        .find(|object| {
+            object.pos() == objects[PLAYER].pos() &&
+                (object.name == STAIRS_DOWN || object.name == STAIRS_UP)
+        })
+        .map(|object| object.name.clone());
+    match stairs.as_deref() {
+        Some(STAIRS_DOWN) => {
+            let level = game.dungeon_level + 1;
+            change_level(level, objects, game);
+        }
+        Some(STAIRS_UP) if game.dungeon_level > 1 => {
+            let level = game.dungeon_level - 1;
+            change_level(level, objects, game);
+        }
+        Some(_) => {
+            game.log.add("The way out is blocked, there is no turning back.",
+                         colors::RED);
+        }
+        None => {}
+    }
+}
+
+/// Leave the current floor as it is and go to another one, generating it if
+/// it wasn't visited yet
+fn change_level(level: u32, objects: &mut Vec<Object>, game: &mut Game) {
+    let going_down = level > game.dungeon_level;
+    // everything but the player stays behind
+    let floor = Level {
+        map: std::mem::take(&mut game.map),
+        objects: objects.split_off(PLAYER + 1),
+    };
+    game.levels.insert(game.dungeon_level, floor);
+    game.dungeon_level = level;
+    // nobody down there heard what happened up here
+    game.noises.clear();
+
+    match game.levels.remove(&level) {
+        Some(floor) => {
+            game.map = floor.map;
+            objects.extend(floor.objects);
+            // arrive on the stairs at the other end
+            let arrival = if going_down { STAIRS_UP } else { STAIRS_DOWN };
+            let arrival = objects.iter()
+                .find(|object| object.name == arrival)
+                .map(|object| object.pos());
+            if let Some((x, y)) = arrival {
+                objects[PLAYER].set_pos(x, y);
+            }
+            game.log.add(format!("You are back on level {}.", level), colors::LIGHT_CYAN);
+        }
+        None => {
+            game.log.add("Entered a safezone on the stairs, you recovered some health",
+                         colors::VIOLET);
+            let heal_hp = objects[PLAYER].max_hp(game) / 2;
+            objects[PLAYER].heal(heal_hp, game);
+
+            game.log.add("Leaving the safezone, you are now in a new level.",
+                         colors::RED);
+            game.map = make_map(objects, level, &game.catalog, &mut game.rng);
+        }
+    }
+    initialize_fov(&game.map, &mut game.fov);
+}
+
+fn handle_action(action: PlayerAction, objects: &mut Vec<Object>,
+                 game: &mut Game) -> TurnResult {
+    use PlayerAction::*;
+    use TurnResult::*;
+
+    if !objects[PLAYER].alive {
+        return DidntTakeTurn;
+    }
+    let takes_time = match action {
+        Move { .. } | Run { .. } | Wait | UseItem { .. } | CloseDoor { .. } | Search => true,
+        PickUp | DropItem { .. } | TakeStairs | LevelUp(_) => false,
+    };
+    if takes_time && objects[PLAYER].has_status(StatusKind::Stun) {
+        game.log.add("You are stunned and can't act!", colors::LIGHT_GREEN);
+        objects[PLAYER].spend_energy(ActionKind::Wait);
+        return TookTurn;
+    }
+    match action {
+        Move { dx, dy } | Run { dx, dy } => {
+            // a confused player stumbles in a random direction
+            let (dx, dy) = if objects[PLAYER].has_status(StatusKind::Confusion) {
+                DIRECTIONS[game.rng.gen_range(0, DIRECTIONS.len())]
+            } else {
+                (dx, dy)
+            };
+            let mut kind = player_move_or_attack(dx, dy, game, objects);
+            if let Run { .. } = action {
+                // only a plain step goes faster, but anything is heard
+                if kind == ActionKind::Move {
+                    kind = ActionKind::Run;
+                }
+                let (x, y) = objects[PLAYER].pos();
+                make_noise(x, y, RUN_NOISE, game);
+            }
+            objects[PLAYER].spend_energy(kind);
+            drop_held_items(objects, game);
+            if fell_in_pit(objects, game) {
+                let level = game.dungeon_level + 1;
+                change_level(level, objects, game);
+            }
+            TookTurn
+        }
+        Wait => {
+            // do nothing, i.e. wait for the monster to come to you
+            objects[PLAYER].spend_energy(ActionKind::Wait);
+            TookTurn
+        }
+        PickUp => {
+            // pick up an item
+            let item_id = objects.iter().position(|object| {
+                object.pos() == objects[PLAYER].pos() && object.item.is_some()
+            });
+            if let Some(item_id) = item_id {
+                pick_item_up(item_id, objects, game);
+            }
+            DidntTakeTurn
+        }
+        UseItem { inventory_id, target } => {
+            if inventory_id >= game.inventory.len() {
+                return DidntTakeTurn;
+            }
+            match use_item(inventory_id, target, game, objects) {
+                Some(kind) => {
+                    objects[PLAYER].spend_energy(kind);
+                    TookTurn
+                }
+                None => DidntTakeTurn,
+            }
+        }
+        DropItem { inventory_id } => {
+            if inventory_id < game.inventory.len() {
+                drop_item(inventory_id, game, objects);
+            }
+            DidntTakeTurn
+        }
+        CloseDoor { dx, dy } => {
+            let (x, y) = (objects[PLAYER].x + dx, objects[PLAYER].y + dy);
+            if close_door(x, y, objects, game) {
+                objects[PLAYER].spend_energy(ActionKind::Move);
+                TookTurn
+            } else {
+                DidntTakeTurn
+            }
+        }
+        Search => {
+            search(objects, game);
+            objects[PLAYER].spend_energy(ActionKind::Wait);
+            TookTurn
+        }
+        TakeStairs => {
+            take_stairs(objects, game);
+            DidntTakeTurn
+        }
+        LevelUp(stat) => {
+            level_up(stat, objects, game);
+            DidntTakeTurn
+        }
+    }
+}
+
+/// Run the player's action and, if it took time, let the monsters act until
+/// the player can act again.
+pub fn play_turn(action: PlayerAction, objects: &mut Vec<Object>,
+                 game: &mut Game) -> TurnResult {
+    game.history.push(action);
+    let (last_seen, level) = (objects[PLAYER].pos(), game.dungeon_level);
+    let result = handle_action(action, objects, game);
+    // the FOV is still the one from before the action, but picking up items
+    // moves the objects around
+    let hunters = hunters_in_view(objects, game);
+    if result == TurnResult::TookTurn {
+        tick_statuses(PLAYER, objects, game);
+    }
+    update_fov(objects, game);
+    // those who saw the player go out of sight remember where, unless the
+    // player left the level
+    if game.dungeon_level == level {
+        lose_sight(&hunters, last_seen, objects, game);
+    }
+
+    // let monsters take their turn
+    if objects[PLAYER].alive && result == TurnResult::TookTurn {
+        run_until_player_turn(objects, game);
+        // they may have opened doors
+        update_fov(objects, game);
+    }
+    update_goals(objects, game);
+    result
+}
+
+pub fn initialize_fov(map: &Map, fov: &mut FovMap) {
+    // create the Fov map, according to the generated map
+    *fov = FovMap::new(MAP_WIDTH, MAP_HEIGHT);
+    for y in 0..MAP_HEIGHT {
+        for x in 0..MAP_WIDTH {
+            fov.set(x, y,
+                    !map[x as usize][y as usize].block_sight,
+                    !map[x as usize][y as usize].blocked);
+        }
+    }
+}
+
+/// recompute FOV around the player and explore every tile in it
+pub fn update_fov(objects: &[Object], game: &mut Game) {
+    let player = &objects[PLAYER];
+    game.fov.compute_fov(player.x, player.y, TORCH_RADIUS, FOV_LIGHT_WALLS);
+    for y in 0..MAP_HEIGHT {
+        for x in 0..MAP_WIDTH {
+            if game.fov.is_in_fov(x, y) {
+                game.map[x as usize][y as usize].explored = true;
+            }
+        }
+    }
+}
+
+/// the maps for travelling, once the player's turn has come
+fn update_goals(objects: &[Object], game: &mut Game) {
+    game.goals = GoalMaps::new(&game.map, objects, objects[PLAYER].movement);
+}
+
+/// Start a new game. The same seed (and data files) always give the same
+/// dungeon.
+pub fn new_game(seed: u64, catalog: Catalog) -> (Vec<Object>, Game) {
+    // create object representing the player
+    let mut player = Object::new(0, 0, '@', "player", colors::WHITE, true);
+    player.alive = true;
+    player.fighter = Some(Fighter{base_max_hp: 15, hp: 15, xp: 0,
+                                  base_power: 1, base_defense: 0,
+                                  base_speed: NORMAL_SPEED,
+                                  base_accuracy: 0, base_evasion: 0, damage: None,
+                                  damage_type: DamageType::Physical,
+                                  resistances: Resistances::default(), regeneration: 0,
+                                  perception: 0, morale: 0,
+                                  on_death: DeathCallback::Player});
+    // the player gets the first move
+    player.energy = TURN_ENERGY;
+
+    // objects list currently populated only by player
+    let mut objects = vec![player];
+    let level = 1;
+    let mut rng = GameRng::new(seed);
+
+    let mut game = Game {
+        map: make_map(&mut objects, level, &catalog, &mut rng),
+        log: vec![],
+        inventory: vec![],
+        dungeon_level: level,
+        levels: BTreeMap::new(),
+        seed,
+        rng,
+        history: vec![],
+        fov: FovMap::default(),
+        catalog,
+        chase: ChaseMaps::default(),
+        goals: GoalMaps::default(),
+        noises: vec![],
+    };
+
+    // initial equipment
+    let mut dagger = Object::new(0, 0, '-', "dagger", colors::SKY, false);
+    dagger.item = Some(Item::Equipment);
+    dagger.equipment = Some(Equipment {
+        equipped: true,
+        slot: Slot::RightHand,
+        max_hp_bonus: 0,
+        power_bonus: 0,
+        defense_bonus: 0,
+        speed_bonus: 0,
+        damage: Some(Dice { count: 1, sides: 3, bonus: 0 }),
+        resistances: Resistances::default(),
+    });
+    game.inventory.push(dagger);
+
+    initialize_fov(&game.map, &mut game.fov);
+    update_fov(&objects, &mut game);
+    update_goals(&objects, &mut game);
+
+    // welcome message
+    game.log.add("Welcome!", colors::RED);
+    game.log.add(format!("Dungeon seed: {}", seed), colors::LIGHT_GREY);
+
+    (objects, game)
+}
+
+pub fn save_game(path: &str, objects: &[Object], game: &Game)
+                 -> Result<(), Box<dyn Error>> {
+    let save_data = serde_json::to_string(&(objects, game))?;
+    let mut file = File::create(path)?;
+    file.write_all(save_data.as_bytes())?;
+    Ok(())
+}
+
+pub fn load_game(path: &str, catalog: Catalog)
+                 -> Result<(Vec<Object>, Game), Box<dyn Error>> {
+    let mut json_save_state = String::new();
+    let mut file = File::open(path)?;
+    file.read_to_string(&mut json_save_state)?;
+    let (objects, mut game) = serde_json::from_str::<(Vec<Object>, Game)>(&json_save_state)?;
+    game.catalog = catalog;
+    initialize_fov(&game.map, &mut game.fov);
+    update_fov(&objects, &mut game);
+    update_goals(&objects, &mut game);
+    Ok((objects, game))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::Ai;
+    use crate::testing::{add_hunter, empty_level, next_to_player};
+
+    #[test]
+    fn picking_up_an_item_keeps_the_hunters_in_view() {
+        let (mut objects, mut game) = empty_level();
+        let (x, y) = objects[PLAYER].pos();
+        objects.push(game.catalog.items.items[0].spawn(x, y));
+        // the hunter comes last, where picking up the item moves things
+        let tile = next_to_player(&objects, &game);
+        add_hunter("orc", tile, &mut objects, &game);
+
+        play_turn(PlayerAction::PickUp, &mut objects, &mut game);
+        assert_eq!(game.inventory.len(), 2);
+        // still in view, so it keeps fighting instead of searching
+        let orc = objects.iter().find(|object| object.pos() == tile).unwrap();
+        assert_eq!(orc.ai, Some(Ai::Basic));
+    }
+}
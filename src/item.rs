@@ -0,0 +1,344 @@
+use crate::PLAYER;
+use crate::colors;
+use crate::combat::DamageType;
+use crate::game::{Game, MessageLog};
+use crate::object::{Object, Slot};
+use crate::projectile::{trace, Impact};
+use crate::scheduler::ActionKind;
+use crate::status::{add_status, StatusKind};
+
+/// how many items the player can carry
+pub const INVENTORY_SIZE: usize = 26;
+/// how long those caught in a fire explosion keep burning
+const AREA_BURN_TURNS: i32 = 2;
+
+/// What an item is for. Items are defined in `items.json`, see `items`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Item {
+    /// used up when used, applying its effect
+    Consumable(Effect),
+    /// put on or taken off when used, see `Object::equipment`
+    Equipment,
+    /// opens a locked door the player walks into, and is used up
+    Key,
+}
+
+/// What a consumable does when used
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Effect {
+    /// heal the player
+    Heal { amount: i32 },
+    /// damage the closest enemy within range
+    Bolt { damage: i32, damage_type: DamageType, range: i32 },
+    /// damage everything around a tile picked by the player, within range if
+    /// there is one
+    Area { damage: i32, damage_type: DamageType, radius: i32, range: Option<i32> },
+    /// put a status on an enemy picked by the player within range, or on
+    /// the player if there is no range
+    Status { status: StatusKind, turns: i32, range: Option<i32> },
+}
+
+/// What the player has to pick before an item can be used. The frontend
+/// asks for it and passes the answer along with `PlayerAction::UseItem`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Targeting {
+    Nothing,
+    Tile { max_range: Option<f32> },
+    Monster { max_range: Option<f32> },
+}
+
+impl Item {
+    pub fn targeting(self) -> Targeting {
+        match self {
+            Item::Consumable(effect) => effect.targeting(),
+            Item::Equipment | Item::Key => Targeting::Nothing,
+        }
+    }
+
+    /// the kind of action using the item is, which decides how long it takes
+    pub fn action_kind(self) -> Option<ActionKind> {
+        match self {
+            Item::Consumable(effect) => Some(effect.action_kind()),
+            // swapping equipment is free
+            Item::Equipment | Item::Key => None,
+        }
+    }
+}
+
+impl Effect {
+    pub fn targeting(self) -> Targeting {
+        use Effect::*;
+        match self {
+            Heal { .. } | Bolt { .. } => Targeting::Nothing,
+            Area { range, .. } => Targeting::Tile { max_range: range.map(|r| r as f32) },
+            Status { range: Some(range), .. } => {
+                Targeting::Monster { max_range: Some(range as f32) }
+            }
+            Status { range: None, .. } => Targeting::Nothing,
+        }
+    }
+
+    pub fn action_kind(self) -> ActionKind {
+        use Effect::*;
+        match self {
+            Heal { .. } | Status { range: None, .. } => ActionKind::DrinkPotion,
+            Bolt { .. } | Area { .. } | Status { .. } => ActionKind::ReadScroll,
+        }
+    }
+}
+
+enum UseResult {
+    UsedUp,
+    UsedAndKept,
+    Cancelled,
+}
+
+/// add to player's inventory and remove from the map
+pub fn pick_item_up(object_id: usize, objects: &mut Vec<Object>, game: &mut Game) {
+    if game.inventory.len() >= INVENTORY_SIZE {
+        game.log.add(format!("Your inventory is full, cannot pick up {}",
+                              objects[object_id].name),
+                     colors::RED);
+    } else {
+        let item = objects.swap_remove(object_id);
+        game.log.add(format!("You picked up {}!", item.name),
+                     colors::GREEN);
+        let index = game.inventory.len();
+        let slot = item.equipment.map(|e| e.slot);
+        game.inventory.push(item);
+
+        // auto equip item if slot is empty
+        if let Some(slot) = slot {
+            if get_equipped_in_slot(slot, &game.inventory).is_none() {
+                game.inventory[index].equip(&mut game.log);
+            }
+        }
+    }
+}
+
+pub fn drop_item(inventory_id: usize,
+                 game: &mut Game,
+                 objects: &mut Vec<Object>) {
+    let mut item = game.inventory.remove(inventory_id);
+    if item.equipment.is_some() {
+        item.dequip(&mut game.log);
+    }
+    item.set_pos(objects[PLAYER].x, objects[PLAYER].y);
+    game.log.add(format!("You dropped a {}", item.name),
+                 colors::YELLOW);
+    objects.push(item);
+}
+
+/// `target` is the tile picked by the player for items that need one.
+/// Returns the kind of action taken, or None if it didn't take any time.
+pub fn use_item(inventory_id: usize, target: Option<(i32, i32)>, game: &mut Game,
+                objects: &mut [Object]) -> Option<ActionKind> {
+    if let Some(item) = game.inventory[inventory_id].item {
+        let result = match item {
+            Item::Consumable(effect) => apply_effect(effect, target, objects, game),
+            Item::Equipment => toggle_equipment(inventory_id, game),
+            Item::Key => {
+                game.log.add("Walk into a locked door to open it.", colors::WHITE);
+                return None;
+            }
+        };
+        match result {
+            UseResult::UsedUp => {
+                // destroy after use, unless it was cancelled for some reason
+                game.inventory.remove(inventory_id);
+                item.action_kind()
+            }
+            UseResult::UsedAndKept => item.action_kind(),
+            UseResult::Cancelled => {
+                game.log.add("Cancelled", colors::WHITE);
+                None
+            }
+        }
+    } else {
+        game.log.add(format!("The {} cannot be used.",
+                              game.inventory[inventory_id].name),
+                     colors::WHITE);
+        None
+    }
+}
+
+/// Runs an effect for the player, who is using an item
+fn apply_effect(effect: Effect, target: Option<(i32, i32)>, objects: &mut [Object],
+                game: &mut Game) -> UseResult {
+    use Effect::*;
+    match effect {
+        Heal { amount } => heal_player(amount, objects, game),
+        Bolt { damage, damage_type, range } => {
+            strike_closest(damage, damage_type, range, objects, game)
+        }
+        Area { damage, damage_type, radius, range } => {
+            // only at a tile the player can see
+            match target {
+                Some((x, y)) if game.fov.is_in_fov(x, y) && in_range(x, y, range, objects) => {
+                    damage_area(x, y, damage, damage_type, radius, objects, game)
+                }
+                _ => UseResult::Cancelled,
+            }
+        }
+        Status { status, turns, range: None } => {
+            add_status(PLAYER, status, turns, 1, objects, game);
+            UseResult::UsedUp
+        }
+        Status { status, turns, range: Some(range) } => {
+            let target = match target {
+                Some(target) => target,
+                None => return UseResult::Cancelled,
+            };
+            match target_monster(target, Some(range as f32), objects, game) {
+                Some(monster_id) => {
+                    add_status(monster_id, status, turns, 1, objects, game);
+                    UseResult::UsedUp
+                }
+                None => {   // no enemy found within maximum range
+                    game.log.add("No enemy is close enough", colors::RED);
+                    UseResult::Cancelled
+                }
+            }
+        }
+    }
+}
+
+fn in_range(x: i32, y: i32, range: Option<i32>, objects: &[Object]) -> bool {
+    range.is_none_or(|range| objects[PLAYER].distance(x, y) <= range as f32)
+}
+
+fn heal_player(amount: i32, objects: &mut [Object], game: &mut Game) -> UseResult {
+    let player = &mut objects[PLAYER];
+    if let Some(fighter) = player.fighter {
+        if fighter.hp == player.max_hp(game) {
+            game.log.add("You are already at full health.", colors::RED);
+            return UseResult::Cancelled;
+        }
+        game.log.add("Your wounds start to feel better!", colors::LIGHT_VIOLET);
+        player.heal(amount, game);
+        return UseResult::UsedUp;
+    }
+    UseResult::Cancelled
+}
+
+fn strike_closest(damage: i32, damage_type: DamageType, range: i32, objects: &mut [Object],
+                  game: &mut Game) -> UseResult {
+    // find the closest enemy (inside a maximum range) and damage it
+    let monster_id = closest_monster(game, range, objects);
+    if let Some(monster_id) = monster_id {
+        // whatever stands in the way takes the bolt instead
+        let from = objects[PLAYER].pos();
+        let monster_id = match trace(from, objects[monster_id].pos(), &game.map, objects) {
+            Impact::Creature(id) => id,
+            Impact::Wall(..) | Impact::Nothing => monster_id,
+        };
+        // zap it!
+        let taken = objects[monster_id].resisted_damage(damage, damage_type, game);
+        game.log.add(format!("A bolt of {} strikes {}! The damage is {} hit points.",
+                              damage_type.noun(), objects[monster_id].name, taken),
+                     colors::LIGHT_BLUE);
+        if let Some(xp) = objects[monster_id].take_damage(damage, damage_type, game) {
+            objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+        }
+        UseResult::UsedUp
+    } else {
+        // no enemy found within maximum range
+        game.log.add("No enemy is close enough to strike.", colors::RED);
+        UseResult::Cancelled
+    }
+}
+
+fn damage_area(x: i32, y: i32, damage: i32, damage_type: DamageType, radius: i32,
+               objects: &mut [Object], game: &mut Game) -> UseResult {
+    game.log.add(format!("It explodes in a burst of {} within {} tiles!",
+                         damage_type.noun(), radius),
+                 colors::ORANGE);
+
+    let mut xp_to_gain = 0;
+    for id in 0..objects.len() {
+        if    objects[id].distance(x, y) <= radius as f32
+           && objects[id].fighter.is_some() {
+            let taken = objects[id].resisted_damage(damage, damage_type, game);
+            game.log.add(format!("The {} is caught in the {} for {} hit points.",
+                                  objects[id].name, damage_type.noun(), taken),
+                         colors::ORANGE);
+            match objects[id].take_damage(damage, damage_type, game) {
+                // Don't reward the player for burning themself
+                Some(xp) if id != PLAYER => xp_to_gain += xp,
+                Some(_) => {}
+                // fire sets the survivors alight
+                None if damage_type == DamageType::Fire => {
+                    add_status(id, StatusKind::Burning, AREA_BURN_TURNS, 1, objects, game);
+                }
+                None => {}
+            }
+        }
+    }
+    objects[PLAYER].fighter.as_mut().unwrap().xp += xp_to_gain;
+
+    UseResult::UsedUp
+}
+
+fn toggle_equipment(inventory_id: usize, game: &mut Game) -> UseResult {
+    let equipment = match game.inventory[inventory_id].equipment {
+        Some(equipment) => equipment,
+        None => return UseResult::Cancelled,
+    };
+    if equipment.equipped {
+        game.inventory[inventory_id].dequip(&mut game.log);
+    } else {
+        // if the slot is already being used, dequip it first
+        if let Some(old_equipment) = get_equipped_in_slot(equipment.slot,
+                                                          &game.inventory) {
+            game.inventory[old_equipment].dequip(&mut game.log);
+        }
+        game.inventory[inventory_id].equip(&mut game.log);
+    }
+    UseResult::UsedAndKept
+}
+
+pub fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
+    for (inventory_id, item) in inventory.iter().enumerate() {
+        if item.equipment.as_ref().is_some_and(|e| e.equipped && e.slot == slot) {
+            return Some(inventory_id)
+        }
+    }
+    None
+}
+
+/// find the closest enemy, up to a maximum range within player's FOV
+fn closest_monster(game: &Game, max_range: i32, objects: &mut [Object])
+        -> Option<usize> {
+    let mut closest_enemy = None;
+    // start with slightly more than maximum range
+    let mut closest_dist = (max_range + 1) as f32;
+
+    for (id, object) in objects.iter().enumerate() {
+        if (id != PLAYER) && object.fighter.is_some() && object.ai.is_some() &&
+            game.fov.is_in_fov(object.x, object.y)
+        {
+            // calculate the distance between the player and this object
+            let dist = objects[PLAYER].distance_to(object);
+            // it's closer. Save the enemy
+            if dist < closest_dist {
+                closest_enemy = Some(id);
+                closest_dist = dist;
+            }
+        }
+    }
+    closest_enemy
+}
+
+/// returns the monster standing on the target tile, if it is inside FOV and
+/// (optionally) in range of the player
+fn target_monster(target: (i32, i32), max_range: Option<f32>, objects: &[Object],
+                  game: &Game) -> Option<usize> {
+    let (x, y) = target;
+    let in_range = max_range.is_none_or(|range| objects[PLAYER].distance(x, y) <= range);
+    if !in_range || !game.fov.is_in_fov(x, y) {
+        return None;
+    }
+    objects.iter().enumerate().position(|(id, obj)| {
+        obj.pos() == (x, y) && obj.fighter.is_some() && id != PLAYER
+    })
+}
@@ -0,0 +1,193 @@
+//! Item definitions, loaded from `items.json`. What an item does is described
+//! as data (an `Effect`, equipment bonuses, or being a key), so new items
+//! don't need any code.
+
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+use crate::colors::Color;
+use crate::data::Fields;
+use crate::item::{Effect, Item};
+use crate::map::Transition;
+use crate::object::{Equipment, Object, Slot};
+use crate::status::StatusKind;
+
+#[derive(Clone, Debug)]
+pub struct ItemDef {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub item: Item,
+    pub equipment: Option<Equipment>,
+    /// chance of the item appearing, by dungeon level
+    pub spawn_weight: Vec<Transition>,
+}
+
+impl ItemDef {
+    pub fn spawn(&self, x: i32, y: i32) -> Object {
+        let mut item = Object::new(x, y, self.glyph, &self.name, self.color, false);
+        item.item = Some(self.item);
+        item.equipment = self.equipment;
+        item.always_visible = true;
+        item
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ItemTable {
+    /// maximum number of items per room, by dungeon level
+    pub max_items: Vec<Transition>,
+    pub items: Vec<ItemDef>,
+}
+
+pub fn parse_items(text: &str) -> Result<ItemTable, String> {
+    let root: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let root = Fields::new("top level", &root, &["max_items", "items"])?;
+    let max_items = root.transitions("max_items")?;
+
+    let mut items = vec![];
+    let mut names = HashSet::new();
+    for (index, value) in root.array("items")?.iter().enumerate() {
+        let context = format!("item {}", index + 1);
+        let item = parse_item(&context, value)?;
+        if !names.insert(item.name.clone()) {
+            return Err(format!("{}: field `name`: \"{}\" is defined twice",
+                               context, item.name));
+        }
+        items.push(item);
+    }
+    if items.is_empty() {
+        return Err("field `items`: at least one item is needed".into());
+    }
+    Ok(ItemTable { max_items, items })
+}
+
+fn parse_item(context: &str, value: &Value) -> Result<ItemDef, String> {
+    let fields = Fields::new(context, value,
+                             &["name", "glyph", "color", "effect", "equipment", "key",
+                               "spawn_weight"])?;
+    let name = fields.string("name")?;
+    if name.is_empty() {
+        return Err(format!("{}: field `name` is empty", context));
+    }
+    // name the item in the errors that follow
+    let context = format!("{} ({})", context, name);
+    let fields = fields.with_context(&context);
+
+    let is_key = fields.has("key") && fields.bool("key")?;
+    let (item, equipment) = match (fields.has("effect"), fields.has("equipment"), is_key) {
+        (true, false, false) => {
+            let effect = parse_effect(&format!("{}: field `effect`", context),
+                                      fields.get("effect")?)?;
+            (Item::Consumable(effect), None)
+        }
+        (false, true, false) => {
+            let equipment = parse_equipment(&format!("{}: field `equipment`", context),
+                                            fields.get("equipment")?)?;
+            (Item::Equipment, Some(equipment))
+        }
+        (false, false, true) => (Item::Key, None),
+        (false, false, false) => {
+            return Err(format!("{}: an item needs an `effect`, `equipment` or `key`",
+                               context));
+        }
+        _ => {
+            return Err(format!("{}: an item has only one of `effect`, `equipment` and \
+                                `key`", context));
+        }
+    };
+    Ok(ItemDef {
+        name,
+        glyph: fields.glyph("glyph")?,
+        color: fields.color("color")?,
+        item,
+        equipment,
+        spawn_weight: fields.transitions("spawn_weight")?,
+    })
+}
+
+fn parse_effect(context: &str, value: &Value) -> Result<Effect, String> {
+    // the fields allowed depend on the type, so look at it first
+    let kind = Fields::new(context, value, &["type", "amount", "damage", "damage_type",
+                                             "radius", "range", "status", "turns"])?
+        .string("type")?;
+    let effect = match kind.as_str() {
+        "heal" => {
+            let fields = Fields::new(context, value, &["type", "amount"])?;
+            Effect::Heal { amount: fields.int("amount", 1)? }
+        }
+        "bolt" => {
+            let fields = Fields::new(context, value,
+                                     &["type", "damage", "damage_type", "range"])?;
+            Effect::Bolt {
+                damage: fields.int("damage", 0)?,
+                damage_type: fields.damage_type("damage_type")?,
+                range: fields.int("range", 1)?,
+            }
+        }
+        "area" => {
+            let fields = Fields::new(context, value,
+                                     &["type", "damage", "damage_type", "radius", "range"])?;
+            Effect::Area {
+                damage: fields.int("damage", 0)?,
+                damage_type: fields.damage_type("damage_type")?,
+                radius: fields.int("radius", 0)?,
+                range: fields.optional_int("range", 1)?,
+            }
+        }
+        "status" => {
+            let fields = Fields::new(context, value, &["type", "status", "turns", "range"])?;
+            let status = match fields.string("status")?.as_str() {
+                "confusion" => StatusKind::Confusion,
+                "poison" => StatusKind::Poison,
+                "burning" => StatusKind::Burning,
+                "regeneration" => StatusKind::Regeneration,
+                "haste" => StatusKind::Haste,
+                "slow" => StatusKind::Slow,
+                "stun" => StatusKind::Stun,
+                other => return Err(format!("{}: field `status`: unknown status \"{}\", \
+                                             expected \"confusion\", \"poison\", \
+                                             \"burning\", \"regeneration\", \"haste\", \
+                                             \"slow\" or \"stun\"", context, other)),
+            };
+            Effect::Status {
+                status,
+                turns: fields.int("turns", 1)?,
+                // without a range, it is for the player
+                range: fields.optional_int("range", 1)?,
+            }
+        }
+        other => return Err(format!("{}: field `type`: unknown effect \"{}\", expected \
+                                     \"heal\", \"bolt\", \"area\" or \"status\"",
+                                    context, other)),
+    };
+    Ok(effect)
+}
+
+fn parse_equipment(context: &str, value: &Value) -> Result<Equipment, String> {
+    let fields = Fields::new(context, value,
+                             &["slot", "max_hp", "power", "defense", "speed", "damage",
+                               "resistances"])?;
+    let slot = match fields.string("slot")?.as_str() {
+        "left hand" => Slot::LeftHand,
+        "right hand" => Slot::RightHand,
+        "head" => Slot::Head,
+        other => return Err(format!("{}: field `slot`: unknown slot \"{}\", expected \
+                                     \"left hand\", \"right hand\" or \"head\"",
+                                    context, other)),
+    };
+    // bonuses may be negative, for cursed items
+    let bonus = |field| fields.optional_int(field, i32::MIN)
+        .map(|bonus| bonus.unwrap_or(0));
+    Ok(Equipment {
+        slot,
+        equipped: false,
+        max_hp_bonus: bonus("max_hp")?,
+        power_bonus: bonus("power")?,
+        defense_bonus: bonus("defense")?,
+        speed_bonus: bonus("speed")?,
+        damage: fields.optional_dice("damage")?,
+        resistances: fields.resistances("resistances")?,
+    })
+}
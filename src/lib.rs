@@ -0,0 +1,42 @@
+//! Game logic of Bibz Rogue. Nothing in here knows about tcod or any window,
+//! so the simulation can be driven by the frontend binary, by tests or by
+//! bots alike.
+
+extern crate rand;
+extern crate serde;
+#[macro_use] extern crate serde_derive;
+extern crate serde_json;
+
+pub mod ai;
+pub mod bsp;
+pub mod caves;
+pub mod colors;
+pub mod combat;
+pub mod data;
+pub mod dijkstra;
+pub mod doors;
+pub mod fov;
+pub mod game;
+pub mod item;
+pub mod items;
+pub mod map;
+pub mod mapgen;
+pub mod monsters;
+pub mod noise;
+pub mod object;
+pub mod path;
+pub mod projectile;
+pub mod replay;
+pub mod rng;
+pub mod scheduler;
+pub mod status;
+pub mod terrain;
+pub mod traps;
+pub mod travel;
+pub mod vaults;
+
+#[cfg(test)]
+mod testing;
+
+// player will always be the first object
+pub const PLAYER: usize = 0;
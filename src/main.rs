@@ -1,57 +1,43 @@
 extern crate tcod;
-extern crate rand;
-extern crate serde;
-#[macro_use] extern crate serde_derive;
-extern crate serde_json;
-
-// the existing imports
-use std::io::{Read, Write};
-use std::fs::File;
-use std::error::Error;
-use std::cmp;
+extern crate bibz_rouge;
+
+use std::time::{Duration, Instant};
+
 use tcod::console::*;
 use tcod::colors::{self, Color};
-use tcod::map::{Map as FovMap, FovAlgorithm};
 use tcod::input::{self, Event, Key, Mouse};
-use rand::Rng;
-use rand::distributions::{Weighted, WeightedChoice, IndependentSample};
+
+use bibz_rouge::PLAYER;
+use bibz_rouge::ai::Ai;
+use bibz_rouge::colors::Color as GameColor;
+use bibz_rouge::data::Catalog;
+use bibz_rouge::game::{self, can_level_up, level_up_xp, Game, PlayerAction, Stat};
+use bibz_rouge::item::Targeting;
+use bibz_rouge::map::{Door, Terrain, TrapKind, MAP_HEIGHT, MAP_WIDTH, STAIRS_DOWN, STAIRS_UP};
+use bibz_rouge::object::Object;
+use bibz_rouge::path::DIRECTIONS;
+use bibz_rouge::replay::{self, Recorder, Replay, REPLAY_FILE};
+use bibz_rouge::rng::random_seed;
+use bibz_rouge::travel::{Stop, Travel};
 
 const SCREEN_WIDTH: i32 = 80;
 const SCREEN_HEIGHT: i32 = 50;
 const LIMIT_FPS: i32 = 20;
-const MAP_WIDTH: i32 = 80;
-const MAP_HEIGHT: i32 = 43;
-const ROOM_MAX_SIZE: i32 = 10;
-const ROOM_MIN_SIZE: i32 = 6;
-const MAX_ROOMS: i32 = 30;
-const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic;
-const FOV_LIGHT_WALLS: bool = true;
-const TORCH_RADIUS: i32 = 10;
 //sizes and coordinates relevant for the GUI
 const BAR_WIDTH: i32 = 20;
 const PANEL_HEIGHT: i32 = 7;
 const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
-// player will always be the first object
-const PLAYER: usize = 0;
 const MSG_X: i32 = BAR_WIDTH + 2;
 const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
 const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
 const INVENTORY_WIDTH: i32 = 50;
-const HEAL_AMOUNT: i32 = 6;
-const LIGHTNING_DAMAGE: i32 = 5;
-const LIGHTNING_RANGE: i32 = 5;
-const CONFUSE_RANGE: i32 = 7;
-const CONFUSE_NUM_TURNS: i32 = 4;
-const FIREBALL_RADIUS: i32 = 3;
-const FIREBALL_DAMAGE: i32 = 5;
-
-const COLOR_DARK_WALL: Color = Color { r: 0, g: 0, b: 100 };
-const COLOR_LIGHT_WALL: Color = Color { r: 130, g: 110, b: 50 };
-const COLOR_DARK_GROUND: Color = Color { r: 50, g: 50, b: 150 };
-const COLOR_LIGHT_GROUND: Color = Color { r: 200, g: 180, b: 50 };
-
-const LEVEL_UP_BASE: i32 = 10;
-const LEVEL_UP_FACTOR: i32 = 5;
+
+const COLOR_DOOR: Color = Color { r: 191, g: 127, b: 63 };
+const COLOR_LOCKED_DOOR: Color = Color { r: 255, g: 215, b: 0 };
+
+// default delay between two actions when watching a replay
+const REPLAY_SPEED_MS: u64 = 100;
+
 const LEVEL_SCREEN_WIDTH: i32 = 40;
 const CHARACTER_SCREEN_WIDTH: i32 = 30;
 
@@ -59,1000 +45,92 @@ struct Tcod {
     root: Root,
     con: Offscreen,
     panel: Offscreen,
-    fov: FovMap,
     mouse: Mouse,
+    notes: Vec<Note>,
 }
 
-#[derive(Serialize, Deserialize)]
-struct Game {
-    map: Map,
-    log: Messages,
-    inventory: Vec<Object>,
-    dungeon_level: u32,
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-enum Slot {
-    LeftHand,
-    RightHand,
-    Head,
-}
-
-impl std::fmt::Display for Slot {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match *self {
-            Slot::LeftHand => write!(f, "left hand"),
-            Slot::RightHand => write!(f, "right hand"),
-            Slot::Head => write!(f, "head"),
-        }
-    }
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-/// An object that can be equipped, yielding bonus
-struct Equipment {
-    slot: Slot,
-    equipped: bool,
-    max_hp_bonus: i32,
-    power_bonus: i32,
-    defense_bonus: i32,
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-enum Item {
-    Heal,
-    Lightning,
-    Fireball,
-    Confusion,
-    Sword,
-    Shield,
-}
-
-enum UseResult {
-    UsedUp,
-    UsedAndKept,
-    Cancelled,
-}
-
-/// add to player's inventory and remove from the map
-fn pick_item_up(object_id: usize, objects: &mut Vec<Object>, game: &mut Game) {
-    if game.inventory.len() >= 26 {
-        game.log.add(format!("Your inventory is full, cannot pick up {}",
-                              objects[object_id].name),
-                     colors::RED);
-    } else {
-        let item = objects.swap_remove(object_id);
-        game.log.add(format!("You picked up {}!", item.name),
-                     colors::GREEN);
-        let index = game.inventory.len();
-        let slot = item.equipment.map(|e| e.slot);
-        game.inventory.push(item);
-
-        // auto equip item if slot is empty
-        if let Some(slot) = slot {
-            if get_equipped_in_slot(slot, &game.inventory).is_none() {
-                game.inventory[index].equip(&mut game.log);
-            }
-        }
-    }
-}
-
-fn drop_item(inventory_id: usize,
-             game: &mut Game,
-             objects: &mut Vec<Object>) {
-    let mut item = game.inventory.remove(inventory_id);
-    if item.equipment.is_some() {
-        item.dequip(&mut game.log);
-    }
-    item.set_pos(objects[PLAYER].x, objects[PLAYER].y);
-    game.log.add(format!("You dropped a {}", item.name),
-                 colors::YELLOW);
-    objects.push(item);
-}
-
-fn use_item(tcod: &mut Tcod, inventory_id: usize, game: &mut Game, objects:
-            &mut [Object]) {
-    use Item::*;
-    // just call the "use function" if it is defined
-    if let Some(item) = game.inventory[inventory_id].item {
-        let on_use: fn(&mut Tcod, usize, &mut [Object], &mut Game) -> UseResult = match item {
-            Heal => cast_heal,
-            Lightning => cast_lightning,
-            Fireball => cast_fireball,
-            Confusion => cast_confuse,
-            Sword => toggle_equipment,
-            Shield => toggle_equipment,
-        };
-        match on_use(tcod, inventory_id, objects, game) {
-            UseResult::UsedUp => {
-                // destroy after use, unless it was cancelled for some reason
-                game.inventory.remove(inventory_id);
-            }
-            UseResult::UsedAndKept => {}, // do nothing
-            UseResult::Cancelled => {
-                game.log.add("Cancelled", colors::WHITE);
-            }
-        }
-    } else {
-        game.log.add(format!("The {} cannot be used.",
-                              game.inventory[inventory_id].name),
-                     colors::WHITE);
-    }
-}
-
-/// Heals the player
-fn cast_heal(_tcod: &mut Tcod, _inventory_id: usize, objects: &mut [Object],
-             game: &mut Game) -> UseResult
-{
-    // heal the player
-    let player = &mut objects[PLAYER];
-    if let Some(fighter) = player.fighter {
-        if fighter.hp == player.max_hp(game) {
-            game.log.add("You are already at full health.", colors::RED);
-            return UseResult::Cancelled;
-        }
-        game.log.add("Your wounds start to feel better!", colors::LIGHT_VIOLET);
-        player.heal(HEAL_AMOUNT, game);
-        return UseResult::UsedUp;
-    }
-    UseResult::Cancelled
-}
-
-/// Damages nearest enemy
-fn cast_lightning(tcod: &mut Tcod, _inventory_id: usize, objects: &mut [Object],
-                  game: &mut Game) -> UseResult {
-    // find the closest enemy (inside a maximum range) and damage it
-    let monster_id = closest_monster(tcod, LIGHTNING_RANGE, objects);
-    if let Some(monster_id) = monster_id {
-        // zap it!
-        game.log.add(format!("A lightning bolt strikes {} with a loud thunder! \
-                              The damage is {} hit points.",
-                              objects[monster_id].name, LIGHTNING_DAMAGE),
-                     colors::LIGHT_BLUE);
-        if let Some(xp) = objects[monster_id].take_damage(LIGHTNING_DAMAGE, game) {
-            objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
-        }
-        UseResult::UsedUp
-    } else {
-        // no enemy found within maximum range
-        game.log.add("No enemy is close enough to strike.", colors::RED);
-        UseResult::Cancelled
-    }
-}
-
-fn cast_fireball(tcod: &mut Tcod, _inventory_id: usize, objects: &mut [Object],
-                 game: &mut Game) -> UseResult {
-    // ask the player for a target tile to throw a fireball at
-    game.log.add("Left-click a target tile for the fireball, or right-click to cancel.",
-                  colors::LIGHT_CYAN);
-    let (x, y) = match target_tile(tcod, objects, game, None) {
-        Some(tile_pos) => tile_pos,
-        None => return UseResult::Cancelled,
-    };
-    game.log.add(format!("The fireball explodes burning everyting within {} tiles!",
-                          FIREBALL_RADIUS),
-                 colors::ORANGE);
-
-    let mut xp_to_gain = 0;
-    for (id, obj) in objects.iter_mut().enumerate() {
-        if    obj.distance(x, y) <= FIREBALL_RADIUS as f32
-           && obj.fighter.is_some() {
-            game.log.add(format!("The {} gets burned for {} hit points.",
-                                  obj.name, FIREBALL_DAMAGE),
-                         colors::ORANGE);
-            if let Some(xp) = obj.take_damage(FIREBALL_DAMAGE, game) {
-                // Don't reward the player for burning themself
-                if id != PLAYER {
-                    xp_to_gain += xp;
-                }
-            }
-        }
-    }
-    objects[PLAYER].fighter.as_mut().unwrap().xp += xp_to_gain;
-
-    UseResult::UsedUp
-}
-
-/// Confuses nearest enemy
-fn cast_confuse(tcod: &mut Tcod, _inventory_id: usize, objects: &mut [Object],
-                game: &mut Game) -> UseResult {
-    // ask the player for a target to confuse
-    game.log.add("Left-click an enemy to confuse it, or right-click to cancel.",
-                  colors::LIGHT_CYAN);
-    let monster_id = target_monster(tcod, objects, game, Some(CONFUSE_RANGE as f32));
-    if let Some(monster_id) = monster_id {
-        let old_ai = objects[monster_id].ai.take().unwrap_or(Ai::Basic);
-        // replace the monster's AI with a confused one
-        // after some turn, the old AI is restored
-        objects[monster_id].ai = Some(Ai::Confused {
-            previous_ai: Box::new(old_ai),
-            num_turns: CONFUSE_NUM_TURNS,
-        });
-        game.log.add(format!("The {} is confused, he wanders around!",
-                              objects[monster_id].name),
-                     colors::LIGHT_GREEN);
-        UseResult::UsedUp
-    } else {    // no enemy found within maximum range
-        game.log.add("No enemy is close enough", colors::RED);
-        UseResult::Cancelled
-    }
-}
-
-fn toggle_equipment(_tcod: &mut Tcod, inventory_id: usize,
-                     _objects: &mut [Object], game: &mut Game) -> UseResult
-{
-    let equipment = match game.inventory[inventory_id].equipment {
-        Some(equipment) => equipment,
-        None => return UseResult::Cancelled,
-    };
-    if equipment.equipped {
-        game.inventory[inventory_id].dequip(&mut game.log);
-    } else {
-        // if the slot is already being used, dequip it first
-        if let Some(old_equipment) = get_equipped_in_slot(equipment.slot,
-                                                          &game.inventory) {
-            game.inventory[old_equipment].dequip(&mut game.log);
-        }
-        game.inventory[inventory_id].equip(&mut game.log);
-    }
-    UseResult::UsedAndKept
-}
-
-fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
-    for (inventory_id, item) in inventory.iter().enumerate() {
-        if item.equipment.as_ref().map_or(false, |e| e.equipped && e.slot == slot) {
-            return Some(inventory_id)
-        }
-    }
-    None
-}
-
-struct Transition {
-    level: u32,
-    value: u32,
+/// A message from the interface rather than from the game, like a prompt.
+/// It is shown among the game's messages but kept out of `Game.log`, which
+/// a replay has to rebuild from the actions alone
+struct Note {
+    /// how many game messages came before it
+    after: usize,
+    text: String,
+    color: GameColor,
 }
 
-/// Returns a value that depends on level. The table specifies what
-/// value occurs after each level, default is 0.
-fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
-    table.iter()
-        .rev()
-        .find(|transition| level >= transition.level)
-        .map_or(0, |transition| transition.value)
+fn note<T: Into<String>>(tcod: &mut Tcod, game: &Game, text: T, color: GameColor) {
+    tcod.notes.push(Note { after: game.log.len(), text: text.into(), color });
 }
 
-fn level_up(objects: &mut [Object], game: &mut Game, tcod: &mut Tcod) {
-    let player = &mut objects[PLAYER];
-    let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
-
-    // see if the player's experience is enough to level up
-    if player.fighter.as_ref().map_or(0, |f| f.xp) >= level_up_xp {
-        // level up if it is
-        player.level += 1;
-        game.log.add(format!("You feel more powerful! You are now level {}!",
-                             player.level),
-                     colors::YELLOW);
-        let fighter = player.fighter.as_mut().unwrap();
-        let mut choice = None;
-        while choice.is_none() {    // keep asking until a choice is made
-            choice = menu(
-                "Choose a stat to raise:\n",
-                &[format!("Vitality (+5 HP, from {}", fighter.base_max_hp),
-                  format!("Attack (+1 power, from {}", fighter.base_power),
-                  format!("Defense (+1 defense, from {}", fighter.base_defense)],
-                LEVEL_SCREEN_WIDTH, &mut tcod.root);
-        };
-        fighter.xp -= level_up_xp;
-        match choice.unwrap() {
-            0 => {
-                fighter.base_max_hp += 5;
-                fighter.hp += 5;
-            }
-            1 => {
-                fighter.base_power += 1;
-            }
-            2 => {
-                fighter.base_defense += 1;
-            }
-            _ => unreachable!(),
+/// the game's messages with the notes in between, oldest first
+fn messages<'a>(notes: &'a [Note], game: &'a Game) -> Vec<(&'a str, GameColor)> {
+    let mut notes = notes.iter().peekable();
+    let mut messages = vec![];
+    for (index, &(ref text, color)) in game.log.iter().enumerate() {
+        while let Some(note) = notes.next_if(|note| note.after <= index) {
+            messages.push((note.text.as_str(), note.color));
         }
+        messages.push((text.as_str(), color));
     }
+    messages.extend(notes.map(|note| (note.text.as_str(), note.color)));
+    messages
 }
 
-type Messages = Vec<(String, Color)>;
-
-trait MessageLog {
-    fn add<T: Into<String>>(&mut self, message: T, color: Color);
-}
-
-impl MessageLog for Vec<(String, Color)> {
-    fn add<T: Into<String>>(&mut self, message: T, color: Color) {
-        self.push((message.into(), color))
-    }
-}
-
-#[derive(Clone, Copy, Debug, PartialEq)]
-enum PlayerAction {
-    TookTurn,
-    DidntTakeTurn,
+/// What the game loop should do after a key press
+enum Command {
+    Act(PlayerAction),
+    /// start walking on their own, see `travel`
+    Travel(Travel),
+    Nothing,
     Exit,
 }
 
-/// Mutably borrow two *separate* elements from the given slice.
-/// Panics when the indexes are equal or out of bounds.
-fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
-    assert!(first_index != second_index);
-    let split_at_index = cmp::max(first_index, second_index);
-    let (first_slice, second_slice) = items.split_at_mut(split_at_index);
-    if first_index < second_index {
-        (&mut first_slice[first_index], &mut second_slice[0])
-    } else {
-        (&mut second_slice[0], &mut first_slice[second_index])
-    }
-}
-
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
-struct Tile {
-    blocked: bool,
-    explored: bool,
-    block_sight: bool,
-}
-
-impl Tile {
-    pub fn empty() -> Self {
-        Tile{blocked: false, explored: false, block_sight: false}
-    }
-
-    pub fn wall() -> Self {
-        Tile{blocked: true, explored: false,  block_sight: true}
-    }
-}
-
-type Map = Vec<Vec<Tile>>;
-
-/// fill map with "blocked" tiles
-fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
-    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
-
-    //Player is the first element, remove everything else.
-    // Note: works only when the player is the first object!
-    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as * const _);
-    objects.truncate(1);
-
-    let mut rooms = vec![];
-
-    for _ in 0..MAX_ROOMS {
-        // random width and height
-        let w = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        let h = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        // random position without going out of the boundary of the map
-        let x = rand::thread_rng().gen_range(0, MAP_WIDTH - w);
-        let y = rand::thread_rng().gen_range(0, MAP_HEIGHT - h);
-
-        let new_room = Rect::new(x, y, w, h);
-
-        // run through the other rooms and see if they intersect with this one
-        let failed = rooms.iter().any(|other_room| new_room.intersects_with(other_room));
-        if !failed {
-            // this means there are no intersections, so this room is valid
-
-            // "paint" it to the map's tiles
-            create_room(new_room, &mut map);
-
-            // add some content to this room, such as monsters
-            place_objects(new_room, &map, objects, level);
-
-            // center coordinates of the new room, will be useful later
-            let (new_x, new_y) = new_room.center();
-
-            if rooms.is_empty() {
-                // this is the first room, where the player starts at
-                objects[PLAYER].set_pos(new_x, new_y);
-            } else {
-                // all rooms after the first
-                // connect it to the previous room with a tunnel
-
-                // center coordinates of the previous room
-                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
-
-                // draw a coin (random bool value -- either true or false)
-                if rand::random() {
-                    // first move horizontally, then vertically
-                    create_h_tunnel(prev_x, new_x, prev_y, &mut map);
-                    create_v_tunnel(prev_y, new_y, new_x, &mut map);
-                } else {
-                    // first move bertically, then horizontally
-                    create_v_tunnel(prev_y, new_y, prev_x, &mut map);
-                    create_h_tunnel(prev_x, new_x, new_y, &mut map);
-                }
-            }
-
-            // finally, append the new room to the list`
-            rooms.push(new_room);
-        }
-    }
-    // create stairs at the center of the last room
-    let (last_room_x, last_room_y) = rooms[rooms.len() -1].center();
-    let mut stairs = Object::new(last_room_x, last_room_y, '<', "stairs",
-                                 colors::WHITE, false);
-    stairs.always_visible = true;
-    objects.push(stairs);
-
-    map
-}
-
-/// combat-related properties and methods for monster, player, NPC).
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-struct Fighter {
-    base_max_hp: i32,
-    hp: i32,
-    xp: i32,
-    base_power: i32,
-    base_defense: i32,
-    on_death: DeathCallback,
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-enum DeathCallback {
-    Player,
-    Monster,
-}
-
-impl DeathCallback {
-    fn callback(self, object: &mut Object, game: &mut Game) {
-        use DeathCallback::*;
-        let callback: fn(&mut Object, &mut Game) = match self {
-            Player => player_death,
-            Monster => monster_death,
-        };
-        callback(object, game);
-    }
-}
-
-fn player_death(player: &mut Object, game: &mut Game) {
-    // the game has ended!
-    game.log.add("You died!", colors::WHITE);
-
-    // transform player into a corpse!
-    player.char = '%';
-    player.color = colors::DARK_RED;
-}
-
-fn monster_death(monster: &mut Object, game: &mut Game) {
-    // transform into a corpse. It doesn't block, can be attacked and doesn't
-    // move
-    game.log.add(
-        format!("{} is dead! You gain {} experience points",
-                monster.name, monster.fighter.unwrap().xp), colors::GREEN);
-    monster.char = '%';
-    monster.color = colors::DARK_RED;
-    monster.blocks = false;
-    monster.fighter = None;
-    monster.ai = None;
-    monster.name = format!("remains of {}", monster.name);
-}
-
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-enum Ai {
-    Basic,
-    Confused{previous_ai: Box<Ai>, num_turns: i32},
-}
-
-// Generic object: Player, Monster, Item, Stairs
-#[derive(Serialize, Deserialize)]
-struct Object {
-    name: String,
-    blocks: bool,
-    alive: bool,
-    x: i32,
-    y: i32,
-    char: char,
-    color: Color,
-    fighter: Option<Fighter>,
-    ai: Option<Ai>,
-    item: Option<Item>,
-    equipment: Option<Equipment>,
-    always_visible: bool,
-    level: i32,
-}
-
-impl Object {
-    pub fn pos(&self) -> (i32, i32) {
-        (self.x, self.y)
-    }
-
-    pub fn set_pos(&mut self, x: i32, y: i32) {
-        self.x = x;
-        self.y = y;
-    }
-
-    pub fn new(x: i32, y: i32, char: char, name: &str, color: Color, blocks: bool) -> Self {
-        Object {
-            x: x,
-            y: y,
-            char: char,
-            name: name.into(),
-            color: color,
-            blocks: blocks,
-            alive: false,
-            fighter: None,
-            ai: None,
-            item: None,
-            equipment: None,
-            always_visible: false,
-            level: 1,
-        }
-    }
-
-    /// set the color and then draw the character that represents this object at its position
-    pub fn draw(&self, con: &mut Console) {
-        con.set_default_foreground(self.color);
-        con.put_char(self.x, self.y, self.char, BackgroundFlag::None);
-    }
-
-    /// return the distance to another object
-    pub fn distance_to(&self, other: &Object) -> f32 {
-        let dx = other.x - self.x;
-        let dy = other.y - self.y;
-        ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
-    }
-
-    pub fn take_damage(&mut self, damage: i32, game: &mut Game) -> Option<i32> {
-        // apply damage if possible
-        if let Some(fighter) = self.fighter.as_mut() {
-            if damage > 0 {
-                fighter.hp -= damage;
-            }
-        }
-        // check for death
-        if let Some(fighter) = self.fighter {
-            if fighter.hp <= 0 {
-                self.alive = false;
-                fighter.on_death.callback(self, game);
-                return Some(fighter.xp);
-            }
-        }
-        None
-    }
-
-    pub fn max_hp(&self, game: &Game) -> i32 {
-        let base_max_hp = self.fighter.map_or(0, |f| f.base_max_hp);
-        let bonus: i32 = self.get_all_equipped(game).iter().map(|e| e.max_hp_bonus).sum();
-        base_max_hp + bonus
-    }
-
-    pub fn power(&self, game: &Game) -> i32 {
-        let base_power = self.fighter.map_or(0, |f| f.base_power);
-        let bonus: i32 = self.get_all_equipped(game).iter().map(|e| e.power_bonus).sum();
-        base_power + bonus
-    }
-
-    pub fn defense(&self, game: &Game) -> i32 {
-        let base_defense = self.fighter.map_or(0, |f| f.base_defense);
-        let bonus: i32 = self.get_all_equipped(game).iter().map(|e| e.defense_bonus).sum();
-        base_defense + bonus
-    }
-
-    pub fn attack(&mut self, target: &mut Object, game: &mut Game) {
-        // simple attack formula
-        let damage = self.power(game) - target.defense(game);
-        if damage > 0 {
-            // make the target take some damage
-            game.log.add(format!("{} attacks {} for {} hit points.",
-                         self.name, target.name, damage), colors::WHITE);
-            if let Some(xp) = target.take_damage(damage, game) {
-                // yield experience to the player
-                self.fighter.as_mut().unwrap().xp += xp;
-            }
-        } else {
-            game.log.add(format!("{} attacks {} but it has no effect!",
-                    self.name, target.name), colors::WHITE);
-        }
-    }
-
-    /// heal by the given amount, without going over the maximum
-    pub fn heal(&mut self, amount: i32, game: &Game) {
-        let max_hp = self.max_hp(game);
-        if let Some(ref mut fighter) = self.fighter {
-            fighter.hp += amount;
-            if fighter.hp > max_hp {
-                fighter.hp = max_hp;
-            }
-        }
-    }
-
-    /// returns the distance fromm some coordinates
-    pub fn distance(&self, x: i32, y: i32) -> f32  {
-        (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
-    }
-
-    /// Equip object and show a message about it
-    pub fn equip (&mut self, log: &mut Vec<(String, Color)>) {
-        if self.item.is_none() {
-            log.add(format!("Can't equip {:?} because it's not an Item.", self.name),
-                    colors::RED);
-            return
-        };
-        if let Some(ref mut equipment) = self.equipment {
-            if !equipment.equipped {
-                equipment.equipped = true;
-                log.add(format!("Equipped {} on {}.", self.name, equipment.slot),
-                        colors::LIGHT_GREEN);
-            }
-        } else {
-            log.add(format!("Can't equip {:?}. Not an equipment.", self.name),
-                            colors::RED);
-        }
-    }
-
-    /// Dequip object and show a message about it
-    pub fn dequip(&mut self, log: &mut Vec<(String, Color)>) {
-        if self.item.is_none() {
-            log.add(format!("Can't dequip {:?} because it's not an item.", self.name),
-                    colors::RED);
-            return
-        };
-        if let Some(ref mut equipment) = self.equipment {
-            if equipment.equipped {
-                equipment.equipped = false;
-                log.add(format!("Dequipped {} from {}.", self.name, equipment.slot),
-                                colors::LIGHT_YELLOW);
-            }
-        } else {
-            log.add(format!("Can't dequip {:?}. Not an equipment.", self.name),
-                    colors::RED);
-        }
-    }
-
-    /// returns a list of equipped items
-    pub fn get_all_equipped(&self, game: &Game) -> Vec<Equipment> {
-        if self.name == "player" {
-            game.inventory
-                .iter()
-                .filter(|item| {
-                    item.equipment.map_or(false, |e| e.equipped)
-                })
-                .map(|item| item.equipment.unwrap())
-                .collect()
-        } else {
-            vec![]
-        }
-    }
-}
-
-fn move_by(id: usize, dx: i32, dy: i32, game: &Game, objects: &mut [Object]) {
-    let (x, y) = objects[id].pos();
-    if !is_blocked(x + dx, y + dy, &game.map, objects) {
-        objects[id].set_pos(x + dx, y + dy);
-    }
-}
-
-fn move_towards(id: usize, target_x: i32, target_y: i32, game: &Game,
-                objects: &mut [Object]) {
-    // vector from this object to the target, and distance
-    let dx = target_x - objects[id].x;
-    let dy = target_y - objects[id].y;
-    let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
-
-    // normalize it to length 1 (preserving the direction), then round it and
-    // convert to integer so the movement is restricted to the map grid
-    let dx = (dx as f32 / distance).round() as i32;
-    let dy = (dy as f32 / distance).round() as i32;
-    move_by(id, dx, dy, game, objects);
-}
-
-/// handle player movements and attacks
-fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game,
-                         objects: &mut [Object]) {
-    // the player coordinates moving to/attacking
-    let x = objects[PLAYER].x + dx;
-    let y = objects[PLAYER].y + dy;
-
-    // try to find an attackable object there
-    let target_id = objects.iter().position(|object| {
-        object.fighter.is_some() && object.pos() == (x, y)
-    });
-
-    // attack if there is a target. Otherwise, move
-    match target_id {
-        Some(target_id) => {
-            let (player, target) = mut_two(PLAYER, target_id, objects);
-            player.attack(target, game);
-        }
-        None => {
-            move_by(PLAYER, dx, dy, game, objects);
-        }
-    }
-}
-
-fn ai_take_turn(monster_id: usize, game: &mut Game, objects: &mut [Object],
-                fov_map: &FovMap) {
-    use Ai::*;
-    if let Some(ai) = objects[monster_id].ai.take() {
-        let new_ai = match ai {
-            Basic => ai_basic(monster_id, game, objects, fov_map),
-            Confused{previous_ai, num_turns} => ai_confused(monster_id, game,
-                                    objects, previous_ai, num_turns)
-        };
-        objects[monster_id].ai = Some(new_ai);
-    }
-}
-
-fn ai_basic(monster_id: usize, game: &mut Game, objects: &mut [Object],
-                fov_map: &FovMap) -> Ai {
-    // a basic monster takes its turn. If you can see it, it can see you
-    let (monster_x, monster_y) = objects[monster_id].pos();
-    if fov_map.is_in_fov(monster_x, monster_y) {
-        if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
-            // move towards player if far away
-            let (player_x, player_y) = objects[PLAYER].pos();
-            move_towards(monster_id, player_x, player_y, game, objects);
-        } else if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
-            // close enough to attack! (if the player is still alive.)
-            let (monster, player) = mut_two(monster_id, PLAYER, objects);
-            monster.attack(player, game);
-        }
-    }
-    Ai::Basic
-}
-
-fn ai_confused(monster_id: usize, game: &mut Game, objects: &mut [Object],
-               previous_ai: Box<Ai>, num_turns: i32)
-                    -> Ai {
-    // still confused
-    if num_turns >= 0 {
-        // move in a random direction, decrease the number of turns confused
-        move_by(monster_id,
-                rand::thread_rng().gen_range(-1, 2),
-                rand::thread_rng().gen_range(-1, 2),
-                game,
-                objects);
-        Ai::Confused{previous_ai: previous_ai, num_turns: num_turns - 1}
-    } else {    // restore the previous AI
-        game.log.add(format!("The {} is no longer confused!",
-                              objects[monster_id].name),
-                     colors::RED);
-        *previous_ai
-    }
-}
-
-/// find the closest enemy, up to a maximum range within player's FOV
-fn closest_monster(tcod: &Tcod, max_range: i32, objects: &mut [Object])
-        -> Option<usize> {
-    let mut closest_enemy = None;
-    // start with slightly more than maximum range
-    let mut closest_dist = (max_range + 1) as f32;
-
-    for (id, object) in objects.iter().enumerate() {
-        if (id != PLAYER) && object.fighter.is_some() && object.ai.is_some() &&
-            tcod.fov.is_in_fov(object.x, object.y)
-        {
-            // calculate the distance between the player and this object
-            let dist = objects[PLAYER].distance_to(object);
-            // it's closer. Save the enemy
-            if dist < closest_dist {
-                closest_enemy = Some(id);
-                closest_dist = dist;
-            }
-        }
-    }
-    closest_enemy
+/// A replay being watched, one action at a time
+struct Playback {
+    actions: Vec<PlayerAction>,
+    next: usize,
+    delay: Duration,
+    last_step: Instant,
 }
 
-fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32) {
-    let max_monsters = from_dungeon_level(&[
-        Transition {level: 1, value: 2},
-        Transition {level: 2, value: 3},
-        Transition {level: 4, value: 5},
-    ], level);
-    // choose random number of monster
-    let num_monsters = rand::thread_rng().gen_range(0, max_monsters + 1);
-
-    // monster random table
-    let troll_chance = from_dungeon_level(&[
-        Transition {level: 2, value: 1},
-        Transition {level: 3, value: 2},
-        Transition {level: 5, value: 3},
-    ],  level);
-    let monster_chances = &mut [
-        Weighted {weight: 4, item: "orc"},
-        Weighted {weight: troll_chance, item: "troll"},
-    ];
-    let monster_choice = WeightedChoice::new(monster_chances);
-
-    for _ in 0..num_monsters {
-        // chose random spot for this monster
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
-
-        // only place it if the tile is not blocked
-        if !is_blocked(x, y, map, objects) {
-            // generate the monsters
-
-            let mut monster = match monster_choice.ind_sample(&mut rand::thread_rng()) {
-                "orc" => {
-                    // create orc
-                    let mut orc = Object::new(x, y, 'o', "orc", colors::DESATURATED_GREEN, true);
-                    orc.fighter = Some(Fighter{base_max_hp: 4, hp: 4, xp: 2,
-                                               base_power: 3, base_defense: 0,
-                                               on_death: DeathCallback::Monster});
-                    orc.ai = Some(Ai::Basic);
-                    orc
-                }
-                "troll" => {
-                    // create troll
-                    let mut troll = Object::new(x, y, 'T', "troll", colors::DARKER_GREEN, true);
-                    troll.fighter = Some(Fighter{base_max_hp: 5, hp: 5, xp: 3, 
-                                                 base_power: 3, base_defense: 0,
-                                                 on_death: DeathCallback::Monster});
-                    troll.ai = Some(Ai::Basic);
-                    troll
-                }
-                _ => unreachable!(),
-            };
-            monster.alive = true;
-            objects.push(monster);
-        }
-    }
-
-    // maximum number of items per room
-    let max_items = from_dungeon_level(&[
-        Transition {level: 1, value: 1},
-        Transition {level: 3, value: 2},
-    ], level);
-
-    // item random table
-    let item_chances = &mut [
-        Weighted {weight: 6, item: Item::Heal},
-        Weighted {weight: from_dungeon_level(&[Transition{level: 4, value: 3}], level),
-                  item: Item::Lightning},
-        Weighted {weight: from_dungeon_level(&[Transition{level: 4, value: 1}], level),
-                  item: Item::Fireball},
-        Weighted {weight: from_dungeon_level(&[Transition{level: 2, value: 4}], level),
-                  item: Item::Confusion},
-        Weighted {weight: 1, item: Item::Sword},
-        Weighted {weight: 1, item: Item::Shield},
-    ];
-    let item_choice = WeightedChoice::new(item_chances);
-
-    // choose random number of items
-    let num_items = rand::thread_rng().gen_range(0, max_items + 1);
-
-    for _ in 0..num_items {
-        // choose random spot for this item
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
-
-        // only place the item if the tile is not blocked
-        if !is_blocked(x, y, map, objects) {
-            let mut item = match item_choice.ind_sample(&mut rand::thread_rng()) {
-                Item::Heal => {
-                    // create healing potion (40% chance)
-                    let mut object = Object::new(x, y, '!', "Healing Potion",
-                                                colors::VIOLET, false);
-                    object.item = Some(Item::Heal);
-                    object
-                }
-                Item::Lightning => {
-                    // create a lightning bolt scroll (20% chance)
-                    let mut object = Object::new(x, y, '#', "Scroll of Lightning",
-                                                colors::LIGHT_YELLOW, false);
-                    object.item = Some(Item::Lightning);
-                    object
-                }
-                Item::Fireball => {
-                    // create a fireball scroll (20% chance)
-                    let mut object = Object::new(x, y, 'F', "Scroll of Fireball",
-                                                colors::LIGHT_YELLOW, false);
-                    object.item = Some(Item::Fireball);
-                    object
-                }
-                Item::Confusion => {
-                    // create a confusion scroll (20% chance)
-                    let mut object = Object::new(x, y, 'C', "Scroll of Confusion",
-                                                colors::ORANGE, false);
-                    object.item = Some(Item::Confusion);
-                    object
-                }
-                Item::Sword => {
-                    // create a sword
-                    let mut object = Object::new(x, y, '/', "Sword", colors::SKY, false);
-                    object.item = Some(Item::Sword);
-                    object.equipment = Some(Equipment{equipped: false,
-                                                      slot: Slot::RightHand,
-                                                      max_hp_bonus: 0,
-                                                      power_bonus: 1,
-                                                      defense_bonus: 0});
-                    object
-                }
-                Item::Shield => {
-                    // create a shield
-                    let mut object = Object::new(x, y, '[', "Shield", colors::BLUE, false);
-                    object.item = Some(Item::Shield);
-                    object.equipment = Some(Equipment{equipped: false,
-                                                      slot: Slot::LeftHand,
-                                                      max_hp_bonus: 0,
-                                                      power_bonus: 0,
-                                                      defense_bonus: 1});
-                    object
-                }
-            };
-            item.always_visible = true;
-            objects.push(item);
+impl Playback {
+    fn new(replay: Replay, delay: Duration) -> Self {
+        Playback {
+            actions: replay.actions,
+            next: 0,
+            delay,
+            last_step: Instant::now(),
         }
     }
-}
-
-#[derive(Clone, Copy, Debug)]
-struct Rect {
-    x1: i32,
-    y1: i32,
-    x2: i32,
-    y2: i32,
-}
-
-impl Rect {
-    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
-        Rect { x1: x, y1: y, x2: x + w, y2: y + h }
-    }
-
-    pub fn center(&self) -> (i32, i32) {
-        let center_x = (self.x1 + self.x2) / 2;
-        let center_y = (self.y1 + self.y2) / 2;
-        (center_x, center_y)
-    }
-
-    pub fn intersects_with(&self, other: &Rect) -> bool {
-        (self.x1 <= other.x2) && (self.x2 >= other.x1) &&
-            (self.y1 <= other.y2) && (self.y2 >= other.y1)
-    }
-}
 
-fn create_room(room: Rect, map: &mut Map) {
-    for x in (room.x1 + 1)..room.x2 {
-        for y in (room.y1 + 1)..room.y2 {
-            map[x as usize][y as usize] = Tile::empty();
+    /// the next action, once enough time has passed since the previous one
+    fn next_action(&mut self) -> Option<PlayerAction> {
+        if self.is_finished() || self.last_step.elapsed() < self.delay {
+            return None;
         }
+        self.last_step = Instant::now();
+        self.next += 1;
+        Some(self.actions[self.next - 1])
     }
-}
 
-fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
-    for x in cmp::min(x1, x2)..(cmp::max(x1,x2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
+    fn is_finished(&self) -> bool {
+        self.next >= self.actions.len()
     }
 }
 
-fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
-    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
-    }
+/// convert a game color into a tcod one
+fn tcod_color(color: GameColor) -> Color {
+    Color::new(color.r, color.g, color.b)
 }
 
-/// Advance to the next level
-fn next_level(tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut Game) {
-    game.log.add("Entered a safezone on the stairs, you recovered some health",
-                  colors::VIOLET);
-    let heal_hp = objects[PLAYER].max_hp(game) / 2;
-    objects[PLAYER].heal(heal_hp, game);
-
-    game.log.add("Leaving the safezone, you are now in a new level.",
-                 colors::RED);
-    game.dungeon_level += 1;
-    game.map = make_map(objects, game.dungeon_level);
-    initialize_fov(&game.map, tcod);
-}
-
-fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
-    // first test the map tile
-    if map[x as usize][y as usize].blocked {
-        return true;
-    }
-    // now check for any blocking objects
-    objects.iter().any(|object| {
-        object.blocks && object.pos() == (x, y)
-    })
+/// set the color and then draw the character that represents this object at its position
+fn draw_object(object: &Object, con: &mut dyn Console) {
+    con.set_default_foreground(tcod_color(object.color));
+    con.put_char(object.x, object.y, object.char, BackgroundFlag::None);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_bar(panel: &mut Offscreen,
               x: i32,
               y: i32,
@@ -1079,7 +157,7 @@ fn render_bar(panel: &mut Offscreen,
     // add centered text with values
     panel.set_default_foreground(colors::WHITE);
     panel.print_ex(x + total_width / 2, y, BackgroundFlag::None, TextAlignment::Center,
-                   &format!("{}: {}/{}", name, value, maximum));
+                   format!("{}: {}/{}", name, value, maximum));
 }
 
 fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32,
@@ -1114,9 +192,9 @@ fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32,
     // blit the contents of "window" to the root console
     let x = SCREEN_WIDTH / 2 - width / 2;
     let y = SCREEN_HEIGHT / 2 - height / 2;
-    tcod::console::blit(&mut window, (0, 0), (width, height), root, (x, y),
+    tcod::console::blit(&window, (0, 0), (width, height), root, (x, y),
                         1.0, 0.7);
-    
+
     // present the root console to the player and wait for key press
     root.flush();
     let key = root.wait_for_keypress(true);
@@ -1136,7 +214,7 @@ fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32,
 
 /// have a menu with each item of the inventory as an option
 fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option<usize> {
-    let options = if inventory.len() == 0 {
+    let options = if inventory.is_empty() {
         vec!["Intenvtory is empty.".into()]
     } else {
         inventory.iter().map(|item| {
@@ -1153,48 +231,90 @@ fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option
     let inventory_index = menu(header, &options, INVENTORY_WIDTH, root);
 
     // if an item was chosen, return it
-    if inventory.len() > 0 {
+    if !inventory.is_empty() {
         inventory_index
     } else {
         None
     }
 }
 
+/// ask the player which stat to raise
+fn level_up_menu(objects: &[Object], tcod: &mut Tcod) -> Stat {
+    let fighter = objects[PLAYER].fighter.unwrap();
+    let mut choice = None;
+    while choice.is_none() {    // keep asking until a choice is made
+        choice = menu(
+            "Choose a stat to raise:\n",
+            &[format!("Vitality (+5 HP, from {}", fighter.base_max_hp),
+              format!("Attack (+1 power, from {}", fighter.base_power),
+              format!("Defense (+1 defense, from {}", fighter.base_defense)],
+            LEVEL_SCREEN_WIDTH, &mut tcod.root);
+    };
+    match choice.unwrap() {
+        0 => Stat::Vitality,
+        1 => Stat::Attack,
+        2 => Stat::Defense,
+        _ => unreachable!(),
+    }
+}
+
+fn trap_color(kind: TrapKind) -> Color {
+    match kind {
+        TrapKind::Dart => colors::LIGHT_GREY,
+        TrapKind::Teleport => colors::LIGHT_VIOLET,
+        TrapKind::Alarm => colors::YELLOW,
+        TrapKind::Pit => colors::DARK_ORANGE,
+        TrapKind::ConfusionGas => colors::LIGHT_GREEN,
+    }
+}
+
+/// background of a tile, darker outside of field of view
+fn terrain_color(terrain: Terrain, visible: bool) -> Color {
+    let (dark, light) = match terrain {
+        Terrain::Wall => ((0, 0, 100), (130, 110, 50)),
+        Terrain::Floor => ((50, 50, 150), (200, 180, 50)),
+        Terrain::ShallowWater => ((40, 60, 140), (60, 120, 200)),
+        Terrain::DeepWater => ((20, 30, 110), (20, 60, 170)),
+        Terrain::Lava => ((100, 30, 30), (230, 80, 20)),
+        Terrain::Chasm => ((10, 10, 30), (20, 20, 20)),
+        Terrain::Rubble => ((60, 60, 120), (150, 130, 90)),
+    };
+    let (r, g, b) = if visible { light } else { dark };
+    Color { r, g, b }
+}
+
 /// Draw all of the objects in the list
-fn render_all(tcod: &mut Tcod, objects: &[Object], game: &mut Game, 
-              fov_recompute: bool) {
-    if fov_recompute {
-        // recompute FOV if needed (the player moved or something)
-        let player = &objects[PLAYER];
-        tcod.fov.compute_fov(player.x, player.y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
-    }
+fn render_all(tcod: &mut Tcod, objects: &[Object], game: &Game) {
     for y in 0..MAP_HEIGHT {
         for x in 0..MAP_WIDTH {
-            let visible = tcod.fov.is_in_fov(x, y);
-            let wall = game.map[x as usize][y as usize].block_sight;
-            let color = match (visible, wall) {
-                // outside of field of view:
-                (false, true) => COLOR_DARK_WALL,
-                (false, false) => COLOR_DARK_GROUND,
-                // inside fov:
-                (true, true) => COLOR_LIGHT_WALL,
-                (true, false) => COLOR_LIGHT_GROUND,
-            };
-            let explored = &mut game.map[x as usize][y as usize].explored;
-            if visible {
-                // since it's visible, explore it
-                *explored = true;
-            }
-            if *explored {
+            let visible = game.fov.is_in_fov(x, y);
+            let tile = &game.map[x as usize][y as usize];
+            // closed doors look like walls
+            let terrain = if tile.block_sight { Terrain::Wall } else { tile.terrain };
+            let color = terrain_color(terrain, visible);
+            if game.map[x as usize][y as usize].explored {
                 // show explored tile only (any visible tile is eplored already)
                 tcod.con.set_char_background(x, y, color, BackgroundFlag::Set);
+                let door = match game.map[x as usize][y as usize].door {
+                    Some(Door::Open) => Some(('\'', COLOR_DOOR)),
+                    Some(Door::Closed) => Some(('+', COLOR_DOOR)),
+                    Some(Door::Locked) => Some(('+', COLOR_LOCKED_DOOR)),
+                    None => None,
+                };
+                let trap = game.map[x as usize][y as usize].trap
+                    .filter(|trap| trap.revealed)
+                    .map(|trap| ('^', trap_color(trap.kind)));
+                if let Some((glyph, glyph_color)) = door.or(trap) {
+                    tcod.con.set_char(x, y, glyph);
+                    tcod.con.set_char_foreground(x, y, glyph_color);
+                }
             }
         }
     }
 
     let mut to_draw: Vec<_> = objects.iter()
                                 .filter(|o| {
-                                    tcod.fov.is_in_fov(o.x, o.y) ||
+                                    game.fov.is_in_fov(o.x, o.y) ||
                                     (    o.always_visible
                                       && game.map[o.x as usize][o.y as usize].explored)
                                 }).collect();
@@ -1202,11 +322,11 @@ fn render_all(tcod: &mut Tcod, objects: &[Object], game: &mut Game,
     to_draw.sort_by(|o1, o2| { o1.blocks.cmp(&o2.blocks) });
     // draw the objects in the list
     for object in &to_draw {
-        object.draw(&mut tcod.con);
+        draw_object(object, &mut tcod.con);
     }
 
     // blit contents of "con" to root console and present it
-    blit(&mut tcod.con, (0, 0), (MAP_WIDTH, MAP_HEIGHT), &mut tcod.root, (0, 0),
+    blit(&tcod.con, (0, 0), (MAP_WIDTH, MAP_HEIGHT), &mut tcod.root, (0, 0),
          1.0, 1.0);
 
     // prepare to render the GUI panel
@@ -1215,13 +335,13 @@ fn render_all(tcod: &mut Tcod, objects: &[Object], game: &mut Game,
 
     // print game messages
     let mut y = MSG_HEIGHT as i32;
-    for &(ref msg, color) in game.log.iter().rev() {
+    for (msg, color) in messages(&tcod.notes, game).into_iter().rev() {
         let msg_height = tcod.panel.get_height_rect(MSG_X, y, MSG_WIDTH, 0, msg);
         y -= msg_height;
         if y < 0 {
             break;
         }
-        tcod.panel.set_default_foreground(color);
+        tcod.panel.set_default_foreground(tcod_color(color));
         tcod.panel.print_rect(MSG_X, y, MSG_WIDTH, 0, msg);
     }
 
@@ -1234,28 +354,54 @@ fn render_all(tcod: &mut Tcod, objects: &[Object], game: &mut Game,
     // display the object names under the mouse
     tcod.panel.set_default_foreground(colors::LIGHT_GREY);
     tcod.panel.print_ex(1, 0, BackgroundFlag::None, TextAlignment::Left,
-                   get_names_under_mouse(tcod.mouse, objects, &tcod.fov));
+                   get_names_under_mouse(tcod.mouse, objects, game));
+
+    tcod.panel.print_ex(1, 3, BackgroundFlag::None, TextAlignment::Left,
+                        format!("Dungeon level: {}", game.dungeon_level));
+
+    // and what the player is suffering from or enjoying
+    tcod.panel.print_rect(1, 4, BAR_WIDTH, PANEL_HEIGHT - 4,
+                          status_list(&objects[PLAYER], ", "));
 
     // blit contents of "con" to root console and present it
-    blit(&mut tcod.panel, (0, 0), (SCREEN_WIDTH, PANEL_HEIGHT), &mut tcod.root,
+    blit(&tcod.panel, (0, 0), (SCREEN_WIDTH, PANEL_HEIGHT), &mut tcod.root,
          (0, PANEL_Y), 1.0, 1.0);
+}
 
-    tcod.panel.print_ex(1, 3, BackgroundFlag::None, TextAlignment::Left,
-                        format!("Dungeon level: {}", game.dungeon_level));
+/// the statuses of the object with their turns left, as "Poisoned (3)"
+fn status_list(object: &Object, separator: &str) -> String {
+    object.statuses
+        .iter()
+        .map(|status| format!("{} ({})", status.kind.name(), status.turns))
+        .collect::<Vec<_>>()
+        .join(separator)
 }
 
 /// return a string with names of all objects under the mouse
-fn get_names_under_mouse(mouse: Mouse, objects: &[Object], fov_map: &FovMap) -> String {
+fn get_names_under_mouse(mouse: Mouse, objects: &[Object], game: &Game) -> String {
     let (x, y) = (mouse.cx as i32, mouse.cy as i32);
 
     // create a list with the names of all the objects at the mouse's
-    // coordinates and in FOV
-    let names = objects
+    // coordinates and in FOV, saying if the monsters are awake
+    let mut names = objects
          .iter()
-         .filter(|obj| {obj.pos() == (x, y) && fov_map.is_in_fov(obj.x, obj.y)})
-         .map(|obj| obj.name.clone())
+         .filter(|obj| {obj.pos() == (x, y) && game.fov.is_in_fov(obj.x, obj.y)})
+         .map(|obj| match obj.ai {
+             Some(Ai::Searching{..}) => format!("{} (searching)", obj.name),
+             Some(Ai::Fleeing{..}) => format!("{} (fleeing)", obj.name),
+             Some(_) => format!("{} ({})", obj.name, obj.alertness.adjective()),
+             None => obj.name.clone(),
+         })
          .collect::<Vec<_>>();
 
+    // and the trap there, once found
+    if x >= 0 && y >= 0 && x < MAP_WIDTH && y < MAP_HEIGHT {
+        let tile = &game.map[x as usize][y as usize];
+        if let Some(trap) = tile.trap.filter(|trap| trap.revealed && tile.explored) {
+            names.push(trap.kind.name().into());
+        }
+    }
+
     // separate by commas
     names.join(", ")
 }
@@ -1264,7 +410,7 @@ fn get_names_under_mouse(mouse: Mouse, objects: &[Object], fov_map: &FovMap) ->
 /// a range) or (None, None) if right-clicked.
 fn target_tile(tcod: &mut Tcod,
                objects: &[Object],
-               game: &mut Game,
+               game: &Game,
                max_range: Option<f32>)
                -> Option<(i32, i32)> {
     use tcod::input::KeyCode::Escape;
@@ -1279,39 +425,39 @@ fn target_tile(tcod: &mut Tcod,
             Some(Event::Key(k)) => key = Some(k),
             None => {}
         }
-        render_all(tcod, objects, game, false);
+        render_all(tcod, objects, game);
 
         let (x, y) = (tcod.mouse.cx as i32, tcod.mouse.cy as i32);
 
         // accept the target if the player clicked in FOV, and in case of range is
         // is specified, if it's in that range
-        let in_fov = (x < MAP_WIDTH) && (y < MAP_HEIGHT) && tcod.fov.is_in_fov(x, y);
-        let in_range = max_range.map_or(
-            true, |range| objects[PLAYER].distance(x, y) <= range);
+        let in_fov = (x < MAP_WIDTH) && (y < MAP_HEIGHT) && game.fov.is_in_fov(x, y);
+        let in_range = max_range.is_none_or(|range| objects[PLAYER].distance(x, y) <= range);
         if tcod.mouse.lbutton_pressed && in_fov && in_range {
             return Some((x, y))
         }
 
-        let escape = key.map_or(false, |k| k.code == Escape);
+        let escape = key.is_some_and(|k| k.code == Escape);
         if tcod.mouse.rbutton_pressed || escape {
             return None // cancel if the player right-clicked or pressed Esc
         }
     }
 }
 
-/// returns a clicked monster inside FOV up to a range, or None if right-clicked
+/// returns the position of a clicked monster inside FOV up to a range, or
+/// None if right-clicked
 fn target_monster(tcod: &mut Tcod,
                   objects: &[Object],
-                  game: &mut Game,
+                  game: &Game,
                   max_range: Option<f32>)
-                  -> Option<usize> {
+                  -> Option<(i32, i32)> {
     loop {
         match target_tile(tcod, objects, game, max_range) {
             Some((x, y)) => {
                 // return the first clicked monster, otherwise continue looping
                 for (id, obj) in objects.iter().enumerate() {
                     if obj.pos() == (x, y) && obj.fighter.is_some() && id != PLAYER {
-                        return Some(id)
+                        return Some((x, y))
                     }
                 }
             }
@@ -1320,85 +466,83 @@ fn target_monster(tcod: &mut Tcod,
     }
 }
 
-fn handle_keys(key: Key, tcod: &mut Tcod, objects: &mut Vec<Object>,
-               game: &mut Game) -> PlayerAction {
+/// ask for the target the item needs, if any
+fn choose_target(inventory_id: usize, tcod: &mut Tcod, objects: &[Object],
+                 game: &mut Game) -> Option<(i32, i32)> {
+    let item = &game.inventory[inventory_id];
+    let targeting = item.item.map_or(Targeting::Nothing, |item| item.targeting());
+    match targeting {
+        Targeting::Nothing => None,
+        Targeting::Tile { max_range } => {
+            let prompt = format!("Left-click a target tile for the {}, or right-click \
+                                  to cancel.", item.name);
+            note(tcod, game, prompt, bibz_rouge::colors::LIGHT_CYAN);
+            target_tile(tcod, objects, game, max_range)
+        }
+        Targeting::Monster { max_range } => {
+            let prompt = format!("Left-click an enemy to use the {} on, or right-click \
+                                  to cancel.", item.name);
+            note(tcod, game, prompt, bibz_rouge::colors::LIGHT_CYAN);
+            target_monster(tcod, objects, game, max_range)
+        }
+    }
+}
+
+fn handle_keys(key: Key, tcod: &mut Tcod, objects: &[Object],
+               game: &mut Game) -> Command {
+    use Command::*;
     use PlayerAction::*;
     use tcod::input::Key;
     use tcod::input::KeyCode::*;
 
     let player_alive = objects[PLAYER].alive;
+    // movement keys, running with shift
+    if let (Some((dx, dy)), true) = (key_direction(key), player_alive) {
+        if key.shift {
+            return Act(Run { dx, dy });
+        }
+        return Act(Move { dx, dy });
+    }
     match (key, player_alive) {
         (Key { code: Escape, .. }, _) => Exit, // exit game
         (Key { code: Enter, alt: true, .. }, _) => {
             // Alt+Enter: toggle fullscreen
             let fullscreen = tcod.root.is_fullscreen();
             tcod.root.set_fullscreen(!fullscreen);
-            DidntTakeTurn
-        }
-        // movement keys
-        (Key { code: Up, .. }, true) | (Key { code: NumPad8, ..}, true) => {
-            player_move_or_attack(0, -1, game, objects);
-            TookTurn
-        }
-        (Key { code: Down, .. }, true) | (Key { code: NumPad2, ..}, true) => {
-            player_move_or_attack(0, 1, game, objects);
-            TookTurn
-        }
-        (Key { code: Left, .. }, true) | (Key { code: NumPad4, ..}, true) => {
-            player_move_or_attack(-1, 0, game, objects);
-            TookTurn
-        }
-        (Key { code: Right, .. }, true) | (Key { code: NumPad6, ..}, true) => {
-            player_move_or_attack(1, 0, game, objects);
-            TookTurn
-        }
-        (Key { code: Home, .. }, true) | (Key { code: NumPad7, ..}, true) => {
-            player_move_or_attack(-1, -1, game, objects);
-            TookTurn
-        }
-        (Key { code: PageUp, .. }, true) | (Key { code: NumPad9, ..}, true) => {
-            player_move_or_attack(1, -1, game, objects);
-            TookTurn
-        }
-        (Key { code: End, .. }, true) | (Key { code: NumPad1, ..}, true) => {
-            player_move_or_attack(-1, 1, game, objects);
-            TookTurn
-        }
-        (Key { code: PageDown, .. }, true) | (Key { code: NumPad3, ..}, true) => {
-            player_move_or_attack(1, 1, game, objects);
-            TookTurn
+            Nothing
         }
         (Key { code: NumPad5, .. }, true) | (Key { printable: 's', ..}, true) => {
-            TookTurn  // do nothing, i.e. wait for the monster to come to you
+            Act(Wait)  // do nothing, i.e. wait for the monster to come to you
         }
 
         (Key { printable: 'g', .. }, true) => {
             // pick up an item
-            let item_id = objects.iter().position(|object| {
-                object.pos() == objects[PLAYER].pos() && object.item.is_some()
-            });
-            if let Some(item_id) = item_id {
-                pick_item_up(item_id, objects, game);
-            }
-            DidntTakeTurn
+            Act(PickUp)
         }
         (Key { printable: 'i', .. }, true) => {
             // show inventory. If an item is selected, use it
             let inventory_index = inventory_menu(
-                &mut game.inventory,
-                "Press the key next to an item to use it, or any other to cancel.\n", 
+                &game.inventory,
+                "Press the key next to an item to use it, or any other to cancel.\n",
                 &mut tcod.root);
-            if let Some(inventory_index) = inventory_index {
-                use_item(tcod, inventory_index, game, objects);
+            match inventory_index {
+                Some(inventory_id) => {
+                    let target = choose_target(inventory_id, tcod, objects, game);
+                    Act(UseItem { inventory_id, target })
+                }
+                None => Nothing,
             }
-            DidntTakeTurn
         }
         (Key { printable: 'c', .. }, true) => {
             // show character infommation
             let player = &objects[PLAYER];
             let level = player.level;
-            let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
+            let level_up_xp = level_up_xp(player);
             if let Some(fighter) = player.fighter.as_ref() {
+                let mut effects = status_list(player, "\n");
+                if effects.is_empty() {
+                    effects = "none".into();
+                }
                 let msg = format!("Character information
 
 Level: {}
@@ -1407,50 +551,119 @@ Experience to next level: {}
 
 Maximum HP: {}
 Power: {}
-Defense: {}",
+Defense: {}
+Speed: {}
+
+Effects:
+{}
+
+Seed: {}",
                                    level, fighter.xp, level_up_xp,
                                    player.max_hp(game), player.power(game),
-                                   player.defense(game));
+                                   player.defense(game), player.speed(game),
+                                   effects, game.seed);
                 msgbox(&msg, CHARACTER_SCREEN_WIDTH, &mut tcod.root);
             }
 
-            DidntTakeTurn
+            Nothing
         }
         (Key {printable: 'd', .. }, true) => {
             // show the inventory; if an item is selected, drop it
             let inventory_index = inventory_menu(
-                &mut game.inventory,
+                &game.inventory,
                 "Press the key next to an item to drop it, or any other keys to cancel.\n",
                 &mut tcod.root);
-            if let Some(inventory_index) = inventory_index {
-                drop_item(inventory_index, game, objects);
+            match inventory_index {
+                Some(inventory_id) => Act(DropItem { inventory_id }),
+                None => Nothing,
             }
-            DidntTakeTurn
         }
-        (Key { printable: '<', .. }, true) => {
-            // go down the stairs, if the player is on them
-            let player_on_stairs = objects.iter().any(|object| {
-                object.pos() == objects[PLAYER].pos() && object.name == "stairs"
-            });
-            if player_on_stairs {
-                next_level(tcod, objects, game);
-            }
-            DidntTakeTurn
+        (Key { printable: 'C', .. }, true) => close_door(tcod, objects, game),
+        (Key { printable: 'S', .. }, true) => Act(Search),
+        (Key { printable: '>', .. }, true) => go_stairs(STAIRS_DOWN, tcod, objects, game),
+        (Key { printable: '<', .. }, true) => go_stairs(STAIRS_UP, tcod, objects, game),
+        (Key { printable: 'x', .. }, true) => {
+            // auto-explore, picking up items on the way
+            Command::Travel(bibz_rouge::travel::Travel::explore(true, objects, game))
         }
-        _ => DidntTakeTurn,
+        (Key { printable: 'X', .. }, true) => {
+            // auto-explore, leaving the items where they are
+            Command::Travel(bibz_rouge::travel::Travel::explore(false, objects, game))
+        }
+        _ => Nothing,
     }
 }
 
-fn initialize_fov(map: &Map, tcod: &mut Tcod) {
-    // create the Fov map, according to the generated map
-    for y in 0..MAP_HEIGHT {
-        for x in 0..MAP_WIDTH {
-            tcod.fov.set(x, y,
-                         !map[x as usize][y as usize].block_sight,
-                         !map[x as usize][y as usize].blocked);
+/// the step a movement key stands for
+fn key_direction(key: Key) -> Option<(i32, i32)> {
+    use tcod::input::KeyCode::*;
+    match key.code {
+        Up | NumPad8 => Some((0, -1)),
+        Down | NumPad2 => Some((0, 1)),
+        Left | NumPad4 => Some((-1, 0)),
+        Right | NumPad6 => Some((1, 0)),
+        Home | NumPad7 => Some((-1, -1)),
+        PageUp | NumPad9 => Some((1, -1)),
+        End | NumPad1 => Some((-1, 1)),
+        PageDown | NumPad3 => Some((1, 1)),
+        _ => None,
+    }
+}
+
+/// close the open door next to the player, asking which one if there are
+/// several
+fn close_door(tcod: &mut Tcod, objects: &[Object], game: &mut Game) -> Command {
+    let (x, y) = objects[PLAYER].pos();
+    let doors: Vec<_> = DIRECTIONS.iter()
+        .cloned()
+        .filter(|&(dx, dy)| {
+            game.map[(x + dx) as usize][(y + dy) as usize].door == Some(Door::Open)
+        })
+        .collect();
+    let direction = match doors.len() {
+        0 => {
+            note(tcod, game, "There is no open door next to you.",
+                 bibz_rouge::colors::LIGHT_GREY);
+            None
         }
+        1 => Some(doors[0]),
+        _ => {
+            note(tcod, game, "Close which door? Press a direction key.",
+                 bibz_rouge::colors::LIGHT_GREY);
+            render_all(tcod, objects, game);
+            tcod.root.flush();
+            key_direction(tcod.root.wait_for_keypress(true))
+        }
+    };
+    match direction {
+        Some((dx, dy)) => Command::Act(PlayerAction::CloseDoor { dx, dy }),
+        None => Command::Nothing,
+    }
+}
+
+/// take the stairs if the player is on them, otherwise walk there first
+fn go_stairs(stairs: &'static str, tcod: &mut Tcod, objects: &[Object], game: &Game)
+             -> Command {
+    let on_stairs = objects.iter().any(|object| {
+        object.pos() == objects[PLAYER].pos() && object.name == stairs
+    });
+    if on_stairs {
+        return Command::Act(PlayerAction::TakeStairs);
+    }
+    match Travel::to_stairs(stairs, objects, game) {
+        Ok(travel) => Command::Travel(travel),
+        Err(stop) => {
+            stopped(stop, tcod, game);
+            Command::Nothing
+        }
+    }
+}
+
+/// tell why travelling stopped, if there is anything to tell
+fn stopped(stop: Stop, tcod: &mut Tcod, game: &Game) {
+    if let Some(text) = stop {
+        note(tcod, game, text, bibz_rouge::colors::LIGHT_GREY);
     }
-    tcod.con.clear();       // unexplored area starts black (default bg color)
 }
 
 fn msgbox(text: &str, width: i32, root: &mut Root) {
@@ -1458,106 +671,186 @@ fn msgbox(text: &str, width: i32, root: &mut Root) {
     menu(text, options, width, root);
 }
 
-fn new_game(tcod: &mut Tcod) -> (Vec<Object>, Game) {
-    // create object representing the player
-    let mut player = Object::new(0, 0, '@', "player", colors::WHITE, true);
-    player.alive = true;
-    player.fighter = Some(Fighter{base_max_hp: 15, hp: 15, xp: 0,
-                                  base_power: 1, base_defense: 0,
-                                  on_death: DeathCallback::Player});
-
-    // objects list currently populated only by player
-    let mut objects = vec![player];
-    let level = 1;
-
-    let mut game = Game {
-        map: make_map(&mut objects, level),
-        log: vec![],
-        inventory: vec![],
-        dungeon_level: level,
-    };
+/// ask the player to type a seed. Returns None if cancelled with Esc.
+fn seed_prompt(width: i32, root: &mut Root) -> Option<u64> {
+    use tcod::input::KeyCode::{Backspace, Enter, Escape};
 
-    // initial equipment
-    let mut dagger = Object::new(0, 0, '-', "dagger", colors::SKY, false);
-    dagger.item = Some(Item::Sword);
-    dagger.equipment = Some(Equipment {
-        equipped: true,
-        slot: Slot::RightHand,
-        max_hp_bonus: 0,
-        power_bonus: 1,
-        defense_bonus: 0,
-    });
-    game.inventory.push(dagger);
+    let mut text = String::new();
+    loop {
+        let height = 3;
+        let mut window = Offscreen::new(width, height);
+        window.set_default_foreground(colors::WHITE);
+        window.print_rect_ex(0, 0, width, height, BackgroundFlag::None,
+                             TextAlignment::Left,
+                             format!("Type a seed and press Enter:\n\n{}_", text));
+        let x = SCREEN_WIDTH / 2 - width / 2;
+        let y = SCREEN_HEIGHT / 2 - height / 2;
+        tcod::console::blit(&window, (0, 0), (width, height), root, (x, y),
+                            1.0, 0.7);
+        root.flush();
+
+        let key = root.wait_for_keypress(true);
+        match key.code {
+            Escape => return None,
+            Enter => {
+                if let Ok(seed) = text.parse() {
+                    return Some(seed);
+                }
+            }
+            Backspace => {
+                text.pop();
+            }
+            _ if key.printable.is_ascii_digit() && text.len() < 20 => {
+                text.push(key.printable);
+            }
+            _ => {}
+        }
+    }
+}
 
-    initialize_fov(&game.map, tcod);
+/// the value following a command line flag, e.g. `--seed <number>`
+fn flag_value(flag: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    let position = args.iter().position(|arg| arg == flag)?;
+    args.get(position + 1).cloned()
+}
 
-    // welcome message
-    game.log.add("Welcome!", colors::RED);
+fn has_flag(flag: &str) -> bool {
+    std::env::args().any(|arg| arg == flag)
+}
 
-    (objects, game)
+/// read the `--seed <number>` command line flag, if given
+fn seed_from_args() -> Option<u64> {
+    match flag_value("--seed").map(|seed| seed.parse()) {
+        Some(Ok(seed)) => Some(seed),
+        Some(Err(_)) => {
+            eprintln!("--seed needs a number, starting with a random seed instead");
+            None
+        }
+        None => None,
+    }
 }
 
-fn save_game(objects: &[Object], game: &Game) -> Result<(), Box<Error>> {
-    let save_data = serde_json::to_string(&(objects, game))?;
-    let mut file = File::create("savegame")?;
-    file.write_all(save_data.as_bytes())?;
-    Ok(())
+/// start writing the game's actions to the replay file
+fn start_recording(tcod: &mut Tcod, game: &Game) -> Option<Recorder> {
+    match Recorder::create(REPLAY_FILE, game) {
+        Ok(recorder) => Some(recorder),
+        Err(e) => {
+            note(tcod, game, format!("Cannot record this game: {}", e),
+                 bibz_rouge::colors::RED);
+            None
+        }
+    }
 }
 
-fn load_game() -> Result<(Vec<Object>, Game), Box<Error>> {
-    let mut json_save_state = String::new();
-    let mut file = File::open("savegame")?;
-    file.read_to_string(&mut json_save_state)?;
-    let result = serde_json::from_str::<(Vec<Object>, Game)>(&json_save_state)?;
-    Ok(result)
+/// play an action and write it to the replay file
+fn take_turn(action: PlayerAction, tcod: &mut Tcod, objects: &mut Vec<Object>,
+             game: &mut Game, recorder: &mut Option<Recorder>) {
+    game::play_turn(action, objects, game);
+    let failed = recorder.as_mut().is_some_and(|r| r.record(action).is_err());
+    if failed {
+        note(tcod, game, "Cannot write to the replay file, recording stopped.",
+             bibz_rouge::colors::RED);
+        *recorder = None;
+    }
 }
 
-fn play_game(objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) {
-    // force FOV "recompute" first time through the game loop
-    let mut previous_player_position = (-1, -1);
+/// The main loop. Actions come from the keyboard, or from `playback` while
+/// a replay is being watched; the player takes over once it is finished.
+fn play_game(objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod,
+             mut playback: Option<Playback>) {
+    use tcod::input::KeyCode::{Escape, NoKey};
 
-    let mut key = Default::default();
+    let mut key: Key = Default::default();
+    tcod.notes.clear();
+    let mut recorder = if playback.is_none() { start_recording(tcod, game) } else { None };
+    let mut travel: Option<Travel> = None;
 
     while !tcod.root.window_closed() {
         // clear the screen of the previous frame
         tcod.con.clear();
 
+        let mut clicked = false;
         match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
-            Some((_, Event::Mouse(m))) => tcod.mouse = m,
+            Some((_, Event::Mouse(m))) => {
+                tcod.mouse = m;
+                clicked = m.lbutton_pressed;
+            }
             Some((_, Event::Key(k))) => key = k,
             _ => key = Default::default(),
         }
 
-        let fov_recompute = previous_player_position != (objects[PLAYER].pos());
-        render_all(tcod, &objects, game, fov_recompute);
+        render_all(tcod, objects, game);
 
         tcod.root.flush();
 
-        // level up if needed
-        level_up(objects, game, tcod);
+        if let Some(replay) = playback.as_mut() {
+            if key.code == Escape {
+                break
+            }
+            if let Some(action) = replay.next_action() {
+                game::play_turn(action, objects, game);
+            }
+            if replay.is_finished() {
+                note(tcod, game, "The replay is over, you are in control now.",
+                     bibz_rouge::colors::LIGHT_CYAN);
+                playback = None;
+                recorder = start_recording(tcod, game);
+            }
+            continue
+        }
 
-        // handle keys and exit the game if needed
-        previous_player_position = objects[PLAYER].pos();
-        let player_action = handle_keys(key, tcod, objects, game);
-        if player_action == PlayerAction::Exit {
-            save_game(objects, game).unwrap();
-            break
+        // level up if needed
+        if objects[PLAYER].alive && can_level_up(objects) {
+            let stat = level_up_menu(objects, tcod);
+            take_turn(PlayerAction::LevelUp(stat), tcod, objects, game, &mut recorder);
+        }
+
+        // a left click on an explored tile walks there
+        if clicked && objects[PLAYER].alive {
+            let (x, y) = (tcod.mouse.cx as i32, tcod.mouse.cy as i32);
+            travel = match Travel::to_tile(x, y, objects, game) {
+                Ok(travel) => Some(travel),
+                Err(stop) => {
+                    stopped(stop, tcod, game);
+                    None
+                }
+            };
+            continue
         }
 
-        // let monsters take their turn
-        if objects[PLAYER].alive && player_action != PlayerAction::DidntTakeTurn {
-            for id in 0..objects.len() {
-                if objects[id].ai.is_some() {
-                    ai_take_turn(id, game, objects, &tcod.fov);
+        // keep travelling until it is over or a key is pressed
+        if let Some(mut current) = travel.take() {
+            if key.code == NoKey {
+                match current.next_action(objects, game) {
+                    Ok(action) => {
+                        take_turn(action, tcod, objects, game, &mut recorder);
+                        travel = Some(current);
+                    }
+                    Err(stop) => stopped(stop, tcod, game),
                 }
             }
+            continue
+        }
+
+        // handle keys and exit the game if needed
+        match handle_keys(key, tcod, objects, game) {
+            Command::Exit => {
+                game::save_game(game::SAVE_FILE, objects, game).unwrap();
+                break
+            }
+            Command::Act(action) => {
+                take_turn(action, tcod, objects, game, &mut recorder);
+            }
+            Command::Travel(new_travel) => travel = Some(new_travel),
+            Command::Nothing => {}
         }
     }
 }
 
-fn main_menu(tcod: &mut Tcod) {
+fn main_menu(tcod: &mut Tcod, catalog: &Catalog, seed: Option<u64>) {
     let img = tcod::image::Image::from_file("menu_background.png")
-            .ok().expect("Background image not found");
+            .expect("Background image not found");
     while !tcod.root.window_closed() {
         // show the background image, at twice the regular console resolution
         tcod::image::blit_2x(&img, (0,0), (-1, -1), &mut tcod.root, (0, 0));
@@ -1571,19 +864,26 @@ fn main_menu(tcod: &mut Tcod) {
                            "playerz_02");
 
         // show options and wait for the player's choice
-        let choices = &["Play a new game", "Continue last game", "Quit"];
+        let choices = &["Play a new game", "New game with seed...",
+                        "Continue last game", "Quit"];
         let choice = menu("", choices, 24, &mut tcod.root);
 
         match choice {
-            Some(0) => {    // new game
-                let (mut objects, mut game) = new_game(tcod);
-                play_game(&mut objects, &mut game, tcod);
+            Some(0) => {    // new game, with the seed from the command line if any
+                let seed = seed.unwrap_or_else(random_seed);
+                let (mut objects, mut game) = game::new_game(seed, catalog.clone());
+                play_game(&mut objects, &mut game, tcod, None);
             }
-            Some(1) => {    // load game
-                match load_game() {
+            Some(1) => {    // new game with a chosen seed
+                if let Some(seed) = seed_prompt(30, &mut tcod.root) {
+                    let (mut objects, mut game) = game::new_game(seed, catalog.clone());
+                    play_game(&mut objects, &mut game, tcod, None);
+                }
+            }
+            Some(2) => {    // load game
+                match game::load_game(game::SAVE_FILE, catalog.clone()) {
                     Ok((mut objects, mut game)) => {
-                        initialize_fov(&game.map, tcod);
-                        play_game(&mut objects, &mut game, tcod);
+                        play_game(&mut objects, &mut game, tcod, None);
                     }
                     Err(_e) => {
                         msgbox("\nNo saved game to load.\n", 24, &mut tcod.root);
@@ -1591,7 +891,7 @@ fn main_menu(tcod: &mut Tcod) {
                     }
                 }
             }
-            Some(2) => {    // quit
+            Some(3) => {    // quit
                 break;
             }
             _ => {}
@@ -1599,7 +899,45 @@ fn main_menu(tcod: &mut Tcod) {
     }
 }
 
+/// play the replay given with `--replay <file>` without opening a window
+/// and print how the game ended
+fn replay_headless(replay: &Replay, catalog: Catalog) {
+    let (objects, game) = replay::play_replay(replay, catalog);
+    let player = &objects[PLAYER];
+    println!("seed {}, {} actions", replay.seed, replay.actions.len());
+    println!("dungeon level {}, player level {}, hp {}/{}{}",
+             game.dungeon_level, player.level,
+             player.fighter.map_or(0, |f| f.hp), player.max_hp(&game),
+             if player.alive { "" } else { " (dead)" });
+    for (msg, _) in game.log.iter().rev().take(10).rev() {
+        println!("  {}", msg);
+    }
+}
+
 fn main() {
+    let catalog = match Catalog::load() {
+        Ok(catalog) => catalog,
+        Err(e) => {
+            eprintln!("Cannot load the game data: {}", e);
+            return;
+        }
+    };
+    let seed = seed_from_args();
+    let replay = match flag_value("--replay").map(|path| Replay::load(&path)) {
+        Some(Ok(replay)) => Some(replay),
+        Some(Err(e)) => {
+            eprintln!("Cannot load the replay: {}", e);
+            return;
+        }
+        None => None,
+    };
+    if let Some(ref replay) = replay {
+        if has_flag("--headless") {
+            replay_headless(replay, catalog);
+            return;
+        }
+    }
+
     let root = Root::initializer()
         .font("arial10x10.png", FontLayout::Tcod)
         .font_type(FontType::Greyscale)
@@ -1610,12 +948,22 @@ fn main() {
     tcod::system::set_fps(LIMIT_FPS);
 
     let mut tcod = Tcod {
-        root: root,
+        root,
         con: Offscreen::new(MAP_WIDTH, MAP_HEIGHT),
         panel: Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT),
-        fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
         mouse: Default::default(),
+        notes: vec![],
     };
 
-    main_menu(&mut tcod);
+    match replay {
+        Some(replay) => {
+            // watch the replay, `--speed` is the delay between actions in ms
+            let speed = flag_value("--speed").and_then(|speed| speed.parse().ok())
+                                             .unwrap_or(REPLAY_SPEED_MS);
+            let (mut objects, mut game) = game::new_game(replay.seed, catalog);
+            let playback = Playback::new(replay, Duration::from_millis(speed));
+            play_game(&mut objects, &mut game, &mut tcod, Some(playback));
+        }
+        None => main_menu(&mut tcod, &catalog, seed),
+    }
 }
@@ -0,0 +1,380 @@
+use rand::Rng;
+use rand::distributions::{Weighted, WeightedChoice, IndependentSample};
+
+use crate::PLAYER;
+use crate::ai::Ai;
+use crate::colors;
+use crate::data::Catalog;
+use crate::mapgen::{Layout, Region};
+use crate::monsters::GroupDef;
+use crate::object::{Movement, Object};
+use crate::path::find_path;
+use crate::rng::GameRng;
+use crate::scheduler::ActionKind;
+use crate::terrain::add_terrain;
+use crate::vaults::place_vaults;
+
+pub const MAP_WIDTH: i32 = 80;
+pub const MAP_HEIGHT: i32 = 43;
+
+/// names of the stairs objects, going one level deeper or back up
+pub const STAIRS_DOWN: &str = "stairs down";
+pub const STAIRS_UP: &str = "stairs up";
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Tile {
+    pub terrain: Terrain,
+    pub blocked: bool,
+    pub explored: bool,
+    pub block_sight: bool,
+    pub door: Option<Door>,
+    pub trap: Option<Trap>,
+}
+
+/// What the ground is made of. Only walls block everyone, how the others can
+/// be crossed depends on the creature, see `terrain`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Terrain {
+    Floor,
+    Wall,
+    ShallowWater,
+    DeepWater,
+    Lava,
+    Chasm,
+    Rubble,
+}
+
+impl Terrain {
+    /// whether creatures moving that way can go on it at all. Walls are
+    /// `Tile.blocked` already
+    pub fn can_cross(self, movement: Movement) -> bool {
+        self != Terrain::Chasm || movement == Movement::Fly
+    }
+
+    /// whether stepping on it hurts creatures moving that way: lava burns,
+    /// and deep water makes walkers drop what they hold
+    pub fn harms(self, movement: Movement) -> bool {
+        match self {
+            Terrain::Lava => movement != Movement::Fly,
+            Terrain::DeepWater => movement == Movement::Walk,
+            _ => false,
+        }
+    }
+
+    /// the kind of move stepping on it is: wading and climbing take longer
+    pub fn move_kind(self, movement: Movement) -> ActionKind {
+        match (self, movement) {
+            (_, Movement::Fly) => ActionKind::Move,
+            (Terrain::ShallowWater, _) | (Terrain::DeepWater, Movement::Walk) => {
+                ActionKind::Wade
+            }
+            (Terrain::Rubble, _) => ActionKind::Climb,
+            _ => ActionKind::Move,
+        }
+    }
+}
+
+/// A door blocks the way and the view like a wall until it is opened, see
+/// `doors`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Door {
+    Open,
+    Closed,
+    /// closed, and only opens with a key
+    Locked,
+}
+
+/// A trap in the floor, going off under whoever steps on it, see `traps`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Trap {
+    pub kind: TrapKind,
+    /// known to the player, and to the monsters who avoid it
+    pub revealed: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TrapKind {
+    Dart,
+    Teleport,
+    Alarm,
+    /// drops the player to the level below
+    Pit,
+    ConfusionGas,
+}
+
+pub const TRAP_KINDS: [TrapKind; 5] = [
+    TrapKind::Dart, TrapKind::Teleport, TrapKind::Alarm, TrapKind::Pit, TrapKind::ConfusionGas,
+];
+
+impl TrapKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            TrapKind::Dart => "dart trap",
+            TrapKind::Teleport => "teleport trap",
+            TrapKind::Alarm => "trip wire",
+            TrapKind::Pit => "pit",
+            TrapKind::ConfusionGas => "gas trap",
+        }
+    }
+}
+
+impl Tile {
+    pub fn empty() -> Self {
+        Tile{terrain: Terrain::Floor, blocked: false, explored: false, block_sight: false,
+             door: None, trap: None}
+    }
+
+    pub fn wall() -> Self {
+        Tile{terrain: Terrain::Wall, blocked: true, explored: false, block_sight: true,
+             door: None, trap: None}
+    }
+
+    pub fn door(door: Door) -> Self {
+        let mut tile = Tile::empty();
+        tile.set_door(door);
+        tile
+    }
+
+    pub fn set_door(&mut self, door: Door) {
+        self.door = Some(door);
+        self.blocked = door != Door::Open;
+        self.block_sight = door != Door::Open;
+    }
+
+    /// whether paths can go through: open tiles, and closed doors as walking
+    /// into them opens them
+    pub fn passable(&self) -> bool {
+        !self.blocked || self.door == Some(Door::Closed)
+    }
+
+    pub fn known_trap(&self) -> bool {
+        self.trap.is_some_and(|trap| trap.revealed)
+    }
+}
+
+pub type Map = Vec<Vec<Tile>>;
+
+#[derive(Clone, Debug)]
+pub struct Transition {
+    pub level: u32,
+    pub value: u32,
+}
+
+/// Returns a value that depends on level. The table specifies what
+/// value occurs after each level, default is 0.
+pub fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
+    table.iter()
+        .rev()
+        .find(|transition| level >= transition.level)
+        .map_or(0, |transition| transition.value)
+}
+
+/// Generate a level with the generator set for it, and fill it
+pub fn make_map(objects: &mut Vec<Object>, level: u32, catalog: &Catalog,
+                rng: &mut GameRng) -> Map {
+    //Player is the first element, remove everything else.
+    // Note: works only when the player is the first object!
+    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as * const _);
+    objects.truncate(1);
+
+    let generator = catalog.dungeon.generator(level).generator();
+    let Layout { mut map, mut regions, start, exit } = generator.generate(rng);
+
+    // the player arrives on the stairs up
+    objects[PLAYER].set_pos(start.0, start.1);
+    let mut stairs = Object::new(start.0, start.1, '<', STAIRS_UP, colors::WHITE, false);
+    stairs.always_visible = true;
+    objects.push(stairs);
+
+    add_terrain(&mut map, level, start, exit, catalog, rng);
+    // the vaults come with their own content, keep the random one out
+    let vaults = place_vaults(&mut map, objects, level, &[start, exit], catalog, rng);
+    for region in &mut regions {
+        region.tiles.retain(|tile| !vaults.contains(tile));
+    }
+    regions.retain(|region| !region.tiles.is_empty());
+
+    // add some content to every region, such as monsters
+    for region in &regions {
+        place_objects(region, &map, objects, level, catalog, rng);
+    }
+
+    let mut stairs = Object::new(exit.0, exit.1, '>', STAIRS_DOWN, colors::WHITE, false);
+    stairs.always_visible = true;
+    objects.push(stairs);
+
+    // hide traps where nothing else is
+    let max_traps = from_dungeon_level(&catalog.dungeon.max_traps, level);
+    for region in &regions {
+        for _ in 0..rng.gen_range(0, max_traps + 1) {
+            let (x, y) = region.random_tile(rng);
+            let kind = TRAP_KINDS[rng.gen_range(0, TRAP_KINDS.len())];
+            let free = map[x as usize][y as usize].terrain == Terrain::Floor &&
+                !map[x as usize][y as usize].blocked &&
+                !objects.iter().any(|object| object.pos() == (x, y));
+            if free {
+                map[x as usize][y as usize].trap = Some(Trap { kind, revealed: false });
+            }
+        }
+    }
+
+    map
+}
+
+/// how far from their leader the followers of a group are placed
+const GROUP_RADIUS: i32 = 2;
+/// random tiles tried for each follower
+const GROUP_TRIES: u32 = 20;
+
+/// What a spawn from the monster table is
+#[derive(Clone, Copy, Debug)]
+enum Spawn {
+    Monster(usize),
+    Group(usize),
+}
+
+fn place_objects(region: &Region, map: &Map, objects: &mut Vec<Object>, level: u32,
+                 catalog: &Catalog, rng: &mut GameRng) {
+    let max_monsters = from_dungeon_level(&catalog.monsters.max_monsters, level);
+    // choose random number of monster
+    let num_monsters = rng.gen_range(0, max_monsters + 1);
+
+    // monster random table, from the spawn weights in the data file. Groups
+    // are in it too
+    let monsters = catalog.monsters.monsters.iter().enumerate()
+        .map(|(index, monster)| (Spawn::Monster(index), &monster.spawn_weight));
+    let groups = catalog.monsters.groups.iter().enumerate()
+        .map(|(index, group)| (Spawn::Group(index), &group.spawn_weight));
+    let monster_chances = &mut monsters.chain(groups)
+        .map(|(spawn, spawn_weight)| Weighted {
+            weight: from_dungeon_level(spawn_weight, level),
+            item: spawn,
+        })
+        .collect::<Vec<_>>();
+    let monster_choice = if monster_chances.iter().any(|chance| chance.weight > 0) {
+        Some(WeightedChoice::new(monster_chances))
+    } else {
+        None    // no monster can appear this deep
+    };
+
+    // one group is enough for a region
+    let mut has_group = false;
+    for _ in 0..num_monsters {
+        let monster_choice = match monster_choice {
+            Some(ref monster_choice) => monster_choice,
+            None => break,
+        };
+        // chose random spot for this monster
+        let (x, y) = region.random_tile(rng);
+
+        // only place it on plain floor that is not blocked
+        if map[x as usize][y as usize].terrain == Terrain::Floor &&
+            !is_blocked(x, y, Movement::Walk, map, objects) {
+            // generate the monsters
+            match monster_choice.ind_sample(rng) {
+                Spawn::Monster(index) => {
+                    objects.push(catalog.monsters.monsters[index].spawn_on_level(x, y, rng));
+                }
+                Spawn::Group(index) if !has_group => {
+                    place_group(&catalog.monsters.groups[index], x, y, map, objects, catalog,
+                                rng);
+                    has_group = true;
+                }
+                Spawn::Group(_) => {}
+            }
+        }
+    }
+
+    let max_items = from_dungeon_level(&catalog.items.max_items, level);
+
+    // item random table, from the spawn weights in the data file
+    let item_chances = &mut catalog.items.items.iter().enumerate()
+        .map(|(index, item)| Weighted {
+            weight: from_dungeon_level(&item.spawn_weight, level),
+            item: index,
+        })
+        .collect::<Vec<_>>();
+    let item_choice = if item_chances.iter().any(|chance| chance.weight > 0) {
+        Some(WeightedChoice::new(item_chances))
+    } else {
+        None    // no item can appear this deep
+    };
+
+    // choose random number of items
+    let num_items = rng.gen_range(0, max_items + 1);
+
+    for _ in 0..num_items {
+        let item_choice = match item_choice {
+            Some(ref item_choice) => item_choice,
+            None => break,
+        };
+        // choose random spot for this item
+        let (x, y) = region.random_tile(rng);
+
+        // only place the item on plain floor that is not blocked
+        if map[x as usize][y as usize].terrain == Terrain::Floor &&
+            !is_blocked(x, y, Movement::Walk, map, objects) {
+            let index = item_choice.ind_sample(rng);
+            objects.push(catalog.items.items[index].spawn(x, y));
+        }
+    }
+}
+
+/// Place the leader of the group on the tile, and its followers around it,
+/// all asleep or awake together. The pack is known by where its leader
+/// started, which no other leader on the level shares
+fn place_group(group: &GroupDef, x: i32, y: i32, map: &Map, objects: &mut Vec<Object>,
+               catalog: &Catalog, rng: &mut GameRng) {
+    let leader_def = match catalog.monsters.get(&group.leader) {
+        Some(leader_def) => leader_def,
+        None => return,
+    };
+    let pack = (x * MAP_HEIGHT + y) as u32;
+    let mut leader = leader_def.spawn_on_level(x, y, rng);
+    let alertness = leader.alertness;
+    leader.ai = leader.ai.take().map(|ai| Ai::Leader { pack, ai: Box::new(ai) });
+    objects.push(leader);
+
+    for follower in &group.followers {
+        let follower_def = match catalog.monsters.get(&follower.monster) {
+            Some(follower_def) => follower_def,
+            None => continue,
+        };
+        for _ in 0..rng.gen_range(follower.min, follower.max + 1) {
+            // a free tile close by, not on the other side of a wall
+            let tile = (0..GROUP_TRIES)
+                .map(|_| (x + rng.gen_range(-GROUP_RADIUS, GROUP_RADIUS + 1),
+                          y + rng.gen_range(-GROUP_RADIUS, GROUP_RADIUS + 1)))
+                .find(|&(tile_x, tile_y)| {
+                    tile_x >= 0 && tile_y >= 0 && tile_x < MAP_WIDTH && tile_y < MAP_HEIGHT &&
+                        map[tile_x as usize][tile_y as usize].terrain == Terrain::Floor &&
+                        !is_blocked(tile_x, tile_y, Movement::Walk, map, objects) &&
+                        find_path((x, y), (tile_x, tile_y), Movement::Walk, map, objects)
+                            .is_some_and(|path| path.len() as i32 <= 2 * GROUP_RADIUS)
+                });
+            if let Some((tile_x, tile_y)) = tile {
+                let mut monster = follower_def.spawn(tile_x, tile_y);
+                monster.alertness = alertness;
+                monster.ai = monster.ai.take().map(|ai| Ai::Follower {
+                    pack,
+                    ai: Box::new(ai),
+                    on_leader_death: group.on_leader_death,
+                });
+                objects.push(monster);
+            }
+        }
+    }
+}
+
+/// whether a creature moving that way can't step on the tile
+pub fn is_blocked(x: i32, y: i32, movement: Movement, map: &Map, objects: &[Object]) -> bool {
+    // first test the map tile
+    let tile = &map[x as usize][y as usize];
+    if tile.blocked || !tile.terrain.can_cross(movement) {
+        return true;
+    }
+    // now check for any blocking objects
+    objects.iter().any(|object| {
+        object.blocks && object.pos() == (x, y)
+    })
+}
@@ -0,0 +1,294 @@
+//! Map generators. Each one only carves the walls and floors of a level and
+//! says where things can go; `map::make_map` then puts the player, stairs,
+//! monsters and items there. Which generator builds which level is set in
+//! `dungeon.json`.
+
+use std::cmp;
+
+use rand::Rng;
+use serde_json::Value;
+
+use crate::bsp::BspGenerator;
+use crate::caves::CaveGenerator;
+use crate::data::Fields;
+use crate::map::{Door, Map, Terrain, Tile, Transition, MAP_HEIGHT, MAP_WIDTH};
+use crate::rng::GameRng;
+use crate::terrain::TerrainPatch;
+
+const ROOM_MAX_SIZE: i32 = 10;
+const ROOM_MIN_SIZE: i32 = 6;
+const MAX_ROOMS: i32 = 30;
+
+/// What a generator made: the map, the areas to fill with monsters and
+/// items, where the player arrives (on the stairs up) and where the stairs
+/// down are.
+pub struct Layout {
+    pub map: Map,
+    pub regions: Vec<Region>,
+    pub start: (i32, i32),
+    pub exit: (i32, i32),
+}
+
+impl Layout {
+    /// rooms are the regions, with the player in the first and the stairs
+    /// down in the last. Doors are put where tunnels go into the rooms
+    pub fn from_rooms(mut map: Map, rooms: &[Rect]) -> Layout {
+        add_doors(&mut map, rooms);
+        Layout {
+            map,
+            regions: rooms.iter().map(|&room| Region::from_rect(room)).collect(),
+            start: rooms[0].center(),
+            exit: rooms[rooms.len() - 1].center(),
+        }
+    }
+}
+
+/// Open tiles where things can be spawned, such as the inside of a room.
+/// Each region gets its own share of monsters and items.
+pub struct Region {
+    pub tiles: Vec<(i32, i32)>,
+}
+
+impl Region {
+    pub fn from_rect(room: Rect) -> Region {
+        let mut tiles = vec![];
+        for x in (room.x1 + 1)..room.x2 {
+            for y in (room.y1 + 1)..room.y2 {
+                tiles.push((x, y));
+            }
+        }
+        Region { tiles }
+    }
+
+    pub fn random_tile(&self, rng: &mut GameRng) -> (i32, i32) {
+        self.tiles[rng.gen_range(0, self.tiles.len())]
+    }
+}
+
+pub trait MapGenerator {
+    fn generate(&self, rng: &mut GameRng) -> Layout;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GeneratorKind {
+    /// random rooms, each joined to the previous one
+    Rooms,
+    /// binary space partition, rooms spread evenly over the map
+    Bsp,
+    /// open caves grown with cellular automata
+    Caves,
+}
+
+impl GeneratorKind {
+    pub fn generator(self) -> Box<dyn MapGenerator> {
+        match self {
+            GeneratorKind::Rooms => Box::new(RoomsGenerator),
+            GeneratorKind::Bsp => Box::new(BspGenerator),
+            GeneratorKind::Caves => Box::new(CaveGenerator),
+        }
+    }
+}
+
+/// How the levels of the dungeon are built
+#[derive(Clone, Debug, Default)]
+pub struct DungeonTable {
+    /// the generator used from each level on, by increasing level
+    pub generators: Vec<(u32, GeneratorKind)>,
+    /// maximum number of traps per region, by dungeon level
+    pub max_traps: Vec<Transition>,
+    /// the patches of water, lava and so on spread over the levels
+    pub terrain: Vec<TerrainPatch>,
+}
+
+impl DungeonTable {
+    /// the generator for a level. Rooms if the table says nothing
+    pub fn generator(&self, level: u32) -> GeneratorKind {
+        self.generators.iter()
+            .rev()
+            .find(|&&(from_level, _)| level >= from_level)
+            .map_or(GeneratorKind::Rooms, |&(_, kind)| kind)
+    }
+}
+
+pub fn parse_dungeon(text: &str) -> Result<DungeonTable, String> {
+    let root: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let root = Fields::new("top level", &root, &["generators", "max_traps", "terrain"])?;
+
+    let mut generators: Vec<(u32, GeneratorKind)> = vec![];
+    for (index, value) in root.array("generators")?.iter().enumerate() {
+        let context = format!("field `generators`, entry {}", index + 1);
+        let entry = Fields::new(&context, value, &["level", "generator"])?;
+        let level = entry.int("level", 1)? as u32;
+        let kind = match entry.string("generator")?.as_str() {
+            "rooms" => GeneratorKind::Rooms,
+            "bsp" => GeneratorKind::Bsp,
+            "caves" => GeneratorKind::Caves,
+            other => return Err(format!("{}: field `generator`: unknown generator \"{}\", \
+                                         expected \"rooms\", \"bsp\" or \"caves\"",
+                                        context, other)),
+        };
+        if generators.last().is_some_and(|&(last, _)| last >= level) {
+            return Err(format!("{}: levels must be increasing", context));
+        }
+        generators.push((level, kind));
+    }
+
+    let mut terrain = vec![];
+    for (index, value) in root.array("terrain")?.iter().enumerate() {
+        let context = format!("field `terrain`, entry {}", index + 1);
+        let entry = Fields::new(&context, value, &["terrain", "max_patches"])?;
+        let kind = match entry.string("terrain")?.as_str() {
+            "shallow water" => Terrain::ShallowWater,
+            "deep water" => Terrain::DeepWater,
+            "lava" => Terrain::Lava,
+            "chasm" => Terrain::Chasm,
+            "rubble" => Terrain::Rubble,
+            other => return Err(format!("{}: field `terrain`: unknown terrain \"{}\", \
+                                         expected \"shallow water\", \"deep water\", \
+                                         \"lava\", \"chasm\" or \"rubble\"",
+                                        context, other)),
+        };
+        terrain.push(TerrainPatch { terrain: kind, max_patches: entry.transitions("max_patches")? });
+    }
+
+    Ok(DungeonTable {
+        generators,
+        max_traps: root.transitions("max_traps")?,
+        terrain,
+    })
+}
+
+/// Tries `MAX_ROOMS` random rooms, keeps the ones that don't overlap and
+/// joins each to the previous one with a tunnel
+pub struct RoomsGenerator;
+
+impl MapGenerator for RoomsGenerator {
+    fn generate(&self, rng: &mut GameRng) -> Layout {
+        // fill map with "blocked" tiles
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        let mut rooms: Vec<Rect> = vec![];
+
+        for _ in 0..MAX_ROOMS {
+            // random width and height
+            let w = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+            let h = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+            // random position without going out of the boundary of the map
+            let x = rng.gen_range(0, MAP_WIDTH - w);
+            let y = rng.gen_range(0, MAP_HEIGHT - h);
+
+            let new_room = Rect::new(x, y, w, h);
+
+            // run through the other rooms and see if they intersect with this one
+            let failed = rooms.iter().any(|other_room| new_room.intersects_with(other_room));
+            if !failed {
+                // this means there are no intersections, so this room is valid
+
+                // "paint" it to the map's tiles
+                create_room(new_room, &mut map);
+
+                // all rooms after the first are connected to the previous room
+                if let Some(previous) = rooms.last() {
+                    connect(previous.center(), new_room.center(), &mut map, rng);
+                }
+
+                // finally, append the new room to the list`
+                rooms.push(new_room);
+            }
+        }
+        Layout::from_rooms(map, &rooms)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Rect {
+    pub x1: i32,
+    pub y1: i32,
+    pub x2: i32,
+    pub y2: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
+        Rect { x1: x, y1: y, x2: x + w, y2: y + h }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.x2 - self.x1
+    }
+
+    pub fn height(&self) -> i32 {
+        self.y2 - self.y1
+    }
+
+    pub fn center(&self) -> (i32, i32) {
+        let center_x = (self.x1 + self.x2) / 2;
+        let center_y = (self.y1 + self.y2) / 2;
+        (center_x, center_y)
+    }
+
+    pub fn intersects_with(&self, other: &Rect) -> bool {
+        (self.x1 <= other.x2) && (self.x2 >= other.x1) &&
+            (self.y1 <= other.y2) && (self.y2 >= other.y1)
+    }
+}
+
+/// carve the inside of the room, its edges stay walls
+pub fn create_room(room: Rect, map: &mut Map) {
+    for x in (room.x1 + 1)..room.x2 {
+        for y in (room.y1 + 1)..room.y2 {
+            map[x as usize][y as usize] = Tile::empty();
+        }
+    }
+}
+
+/// join two points with an L-shaped tunnel, turning either way
+pub fn connect((x1, y1): (i32, i32), (x2, y2): (i32, i32), map: &mut Map,
+               rng: &mut GameRng) {
+    // draw a coin (random bool value -- either true or false)
+    if rng.gen() {
+        // first move horizontally, then vertically
+        create_h_tunnel(x1, x2, y1, map);
+        create_v_tunnel(y1, y2, x2, map);
+    } else {
+        // first move vertically, then horizontally
+        create_v_tunnel(y1, y2, x1, map);
+        create_h_tunnel(x1, x2, y2, map);
+    }
+}
+
+/// a closed door on every opening in the edges of the rooms that has a wall
+/// on each side, so it fits like in a door frame
+fn add_doors(map: &mut Map, rooms: &[Rect]) {
+    for room in rooms {
+        for x in (room.x1 + 1)..room.x2 {
+            for &y in &[room.y1, room.y2] {
+                if !map[x as usize][y as usize].blocked &&
+                    map[(x - 1) as usize][y as usize].blocked &&
+                    map[(x + 1) as usize][y as usize].blocked {
+                    map[x as usize][y as usize] = Tile::door(Door::Closed);
+                }
+            }
+        }
+        for y in (room.y1 + 1)..room.y2 {
+            for &x in &[room.x1, room.x2] {
+                if !map[x as usize][y as usize].blocked &&
+                    map[x as usize][(y - 1) as usize].blocked &&
+                    map[x as usize][(y + 1) as usize].blocked {
+                    map[x as usize][y as usize] = Tile::door(Door::Closed);
+                }
+            }
+        }
+    }
+}
+
+fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
+    for x in cmp::min(x1, x2)..(cmp::max(x1,x2) + 1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
+    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
@@ -0,0 +1,283 @@
+//! Monster definitions, loaded from `monsters.json` so new monsters can be
+//! added without recompiling.
+
+use std::collections::HashSet;
+
+use rand::Rng;
+use serde_json::Value;
+
+use crate::ai::{Ai, LeaderDeath, RangedAttack};
+use crate::colors::Color;
+use crate::combat::{DamageType, Dice, Resistances};
+use crate::data::Fields;
+use crate::map::Transition;
+use crate::noise::Alertness;
+use crate::object::{DeathCallback, Fighter, Movement, Object};
+use crate::projectile::Projectile;
+use crate::scheduler::NORMAL_SPEED;
+
+/// how far a monster notices the player, unless said otherwise
+const DEFAULT_PERCEPTION: i32 = 6;
+
+#[derive(Clone, Debug)]
+pub struct MonsterDef {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub max_hp: i32,
+    pub power: i32,
+    pub defense: i32,
+    pub xp: i32,
+    pub speed: i32,
+    pub accuracy: i32,
+    pub evasion: i32,
+    pub damage: Option<Dice>,
+    pub damage_type: DamageType,
+    pub resistances: Resistances,
+    pub regeneration: i32,
+    pub perception: i32,
+    pub morale: i32,
+    /// chance in percent of the monster being asleep when the level is made
+    pub asleep: u32,
+    pub movement: Movement,
+    pub ai: Ai,
+    /// chance of the monster appearing, by dungeon level
+    pub spawn_weight: Vec<Transition>,
+}
+
+impl MonsterDef {
+    pub fn spawn(&self, x: i32, y: i32) -> Object {
+        let mut monster = Object::new(x, y, self.glyph, &self.name, self.color, true);
+        monster.fighter = Some(Fighter{base_max_hp: self.max_hp, hp: self.max_hp,
+                                       xp: self.xp, base_power: self.power,
+                                       base_defense: self.defense,
+                                       base_speed: self.speed,
+                                       base_accuracy: self.accuracy,
+                                       base_evasion: self.evasion,
+                                       damage: self.damage,
+                                       damage_type: self.damage_type,
+                                       resistances: self.resistances,
+                                       regeneration: self.regeneration,
+                                       perception: self.perception,
+                                       morale: self.morale,
+                                       on_death: DeathCallback::Monster});
+        monster.movement = self.movement;
+        monster.ai = Some(self.ai.clone());
+        monster.alertness = Alertness::Wandering;
+        monster.alive = true;
+        monster
+    }
+
+    /// the monster as found on a new level: asleep or wandering around
+    pub fn spawn_on_level<R: Rng>(&self, x: i32, y: i32, rng: &mut R) -> Object {
+        let mut monster = self.spawn(x, y);
+        if rng.gen_range(0, 100) < self.asleep {
+            monster.alertness = Alertness::Asleep;
+        }
+        monster
+    }
+}
+
+/// Monsters that appear together: a leader, and followers around it
+#[derive(Clone, Debug)]
+pub struct GroupDef {
+    pub name: String,
+    pub leader: String,
+    pub followers: Vec<FollowerDef>,
+    pub on_leader_death: LeaderDeath,
+    /// chance of the group appearing instead of a monster, by dungeon level
+    pub spawn_weight: Vec<Transition>,
+}
+
+/// between `min` and `max` of the monster, in a group
+#[derive(Clone, Debug)]
+pub struct FollowerDef {
+    pub monster: String,
+    pub min: u32,
+    pub max: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MonsterTable {
+    /// maximum number of monsters per room, by dungeon level. A group counts
+    /// as one
+    pub max_monsters: Vec<Transition>,
+    pub monsters: Vec<MonsterDef>,
+    pub groups: Vec<GroupDef>,
+}
+
+impl MonsterTable {
+    pub fn get(&self, name: &str) -> Option<&MonsterDef> {
+        self.monsters.iter().find(|monster| monster.name == name)
+    }
+}
+
+pub fn parse_monsters(text: &str) -> Result<MonsterTable, String> {
+    let root: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let root = Fields::new("top level", &root, &["max_monsters", "monsters", "groups"])?;
+    let max_monsters = root.transitions("max_monsters")?;
+
+    let mut monsters = vec![];
+    let mut names = HashSet::new();
+    for (index, value) in root.array("monsters")?.iter().enumerate() {
+        let context = format!("monster {}", index + 1);
+        let monster = parse_monster(&context, value)?;
+        if !names.insert(monster.name.clone()) {
+            return Err(format!("{}: field `name`: \"{}\" is defined twice",
+                               context, monster.name));
+        }
+        monsters.push(monster);
+    }
+    if monsters.is_empty() {
+        return Err("field `monsters`: at least one monster is needed".into());
+    }
+
+    let mut groups = vec![];
+    if root.has("groups") {
+        for (index, value) in root.array("groups")?.iter().enumerate() {
+            let context = format!("group {}", index + 1);
+            groups.push(parse_group(&context, value, &names)?);
+        }
+    }
+    Ok(MonsterTable { max_monsters, monsters, groups })
+}
+
+/// `names`: the monsters defined, which the group has to be made of
+fn parse_group(context: &str, value: &Value, names: &HashSet<String>)
+               -> Result<GroupDef, String> {
+    let fields = Fields::new(context, value,
+                             &["name", "leader", "followers", "on_leader_death",
+                               "spawn_weight"])?;
+    let context = format!("{} ({})", context, fields.string("name")?);
+    let fields = fields.with_context(&context);
+    let known = |field: &str, name: String| if names.contains(&name) {
+        Ok(name)
+    } else {
+        Err(format!("{}: field `{}`: unknown monster \"{}\"", context, field, name))
+    };
+
+    let leader = known("leader", fields.string("leader")?)?;
+    let mut followers = vec![];
+    for (index, value) in fields.array("followers")?.iter().enumerate() {
+        let follower = Fields::new(&format!("{}: field `followers`, entry {}", context, index + 1),
+                                   value, &["monster", "min", "max"])?;
+        let min = follower.int("min", 0)?;
+        let max = follower.int("max", min)?;
+        followers.push(FollowerDef {
+            monster: known("monster", follower.string("monster")?)?,
+            min: min as u32,
+            max: max as u32,
+        });
+    }
+    let on_leader_death = match fields.string("on_leader_death")?.as_str() {
+        "scatter" => LeaderDeath::Scatter,
+        "enrage" => LeaderDeath::Enrage,
+        other => return Err(format!("{}: field `on_leader_death`: unknown reaction \"{}\", \
+                                     expected \"scatter\" or \"enrage\"", context, other)),
+    };
+    Ok(GroupDef {
+        name: fields.string("name")?,
+        leader,
+        followers,
+        on_leader_death,
+        spawn_weight: fields.transitions("spawn_weight")?,
+    })
+}
+
+fn parse_monster(context: &str, value: &Value) -> Result<MonsterDef, String> {
+    let fields = Fields::new(context, value,
+                             &["name", "glyph", "color", "fighter", "movement", "ai", "ranged",
+                               "asleep", "spawn_weight"])?;
+    let name = fields.string("name")?;
+    if name.is_empty() {
+        return Err(format!("{}: field `name` is empty", context));
+    }
+    // name the monster in the errors that follow
+    let context = format!("{} ({})", context, name);
+    let fields = fields.with_context(&context);
+
+    let fighter = Fields::new(&format!("{}: field `fighter`", context),
+                              fields.get("fighter")?,
+                              &["hp", "power", "defense", "xp", "speed", "accuracy",
+                                "evasion", "damage", "damage_type", "resistances",
+                                "regeneration", "perception", "morale"])?;
+    // walking if not said otherwise
+    let movement = if fields.has("movement") {
+        match fields.string("movement")?.as_str() {
+            "walk" => Movement::Walk,
+            "swim" => Movement::Swim,
+            "fly" => Movement::Fly,
+            other => return Err(format!("{}: field `movement`: unknown movement \"{}\", \
+                                         expected \"walk\", \"swim\" or \"fly\"",
+                                        context, other)),
+        }
+    } else {
+        Movement::Walk
+    };
+    let ai = match fields.string("ai")?.as_str() {
+        "basic" => Ai::Basic,
+        "ranged" => Ai::Ranged(parse_ranged(&context, fields.get("ranged")?)?),
+        other => return Err(format!("{}: field `ai`: unknown AI \"{}\", expected \"basic\" \
+                                     or \"ranged\"", context, other)),
+    };
+    if fields.has("ranged") && ai == Ai::Basic {
+        return Err(format!("{}: field `ranged` is only for the \"ranged\" AI", context));
+    }
+    Ok(MonsterDef {
+        name,
+        glyph: fields.glyph("glyph")?,
+        color: fields.color("color")?,
+        max_hp: fighter.int("hp", 1)?,
+        power: fighter.int("power", 0)?,
+        defense: fighter.int("defense", 0)?,
+        xp: fighter.int("xp", 0)?,
+        speed: fighter.optional_int("speed", 1)?.unwrap_or(NORMAL_SPEED),
+        accuracy: fighter.optional_int("accuracy", i32::MIN)?.unwrap_or(0),
+        evasion: fighter.optional_int("evasion", i32::MIN)?.unwrap_or(0),
+        damage: fighter.optional_dice("damage")?,
+        damage_type: if fighter.has("damage_type") {
+            fighter.damage_type("damage_type")?
+        } else {
+            DamageType::Physical
+        },
+        resistances: fighter.resistances("resistances")?,
+        regeneration: fighter.optional_int("regeneration", 0)?.unwrap_or(0),
+        perception: fighter.optional_int("perception", 0)?.unwrap_or(DEFAULT_PERCEPTION),
+        morale: fighter.optional_int("morale", 0)?.unwrap_or(0),
+        asleep: fields.optional_int_between("asleep", 0, 100)?.unwrap_or(0) as u32,
+        movement,
+        ai,
+        spawn_weight: fields.transitions("spawn_weight")?,
+    })
+}
+
+fn parse_ranged(context: &str, value: &Value) -> Result<RangedAttack, String> {
+    let fields = Fields::new(&format!("{}: field `ranged`", context), value,
+                             &["projectile", "damage", "damage_type", "range", "distance",
+                               "cooldown", "ammo"])?;
+    let damage = match fields.optional_dice("damage")? {
+        Some(damage) => damage,
+        None => return Err(format!("{}: field `damage` is missing", fields.context)),
+    };
+    let range = fields.int("range", 1)?;
+    let distance = fields.int("distance", 1)?;
+    if distance > range {
+        return Err(format!("{}: field `distance` must be at most the range", fields.context));
+    }
+    Ok(RangedAttack {
+        projectile: Projectile {
+            name: fields.string("projectile")?,
+            damage,
+            damage_type: if fields.has("damage_type") {
+                fields.damage_type("damage_type")?
+            } else {
+                DamageType::Physical
+            },
+        },
+        range,
+        distance,
+        cooldown: fields.int("cooldown", 0)?,
+        recharge: 0,
+        ammo: fields.optional_int("ammo", 1)?,
+    })
+}
@@ -0,0 +1,144 @@
+//! Noise, and how monsters notice the player. Monsters start asleep or
+//! wandering, and only come for the player once they notice them: by seeing
+//! them within their perception, by getting hurt, or by hearing something.
+//! Fights, doors, running and alarms make noise. It travels along the ways a
+//! creature could go, so it is heard around corners but not through walls.
+//!
+//! Noises are queued while the action making them goes on, and heard once it
+//! is over, see `hear_noises`.
+
+use crate::PLAYER;
+use crate::ai::Ai;
+use crate::colors;
+use crate::dijkstra::DijkstraMap;
+use crate::game::{Game, MessageLog};
+use crate::object::{Movement, Object};
+use crate::path::{terrain_cost, STRAIGHT_COST};
+
+/// how far noises carry, in tiles
+pub const ATTACK_NOISE: i32 = 6;
+pub const DOOR_NOISE: i32 = 4;
+pub const RUN_NOISE: i32 = 5;
+/// the loudest, but still not heard from the far end of the level
+pub const ALARM_NOISE: i32 = 10;
+
+/// how much a monster notices
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Alertness {
+    /// only noise or pain wakes it up
+    Asleep,
+    /// going about, not knowing about the player
+    Wandering,
+    /// heading for a noise it heard
+    Alerted { x: i32, y: i32 },
+    /// after the player
+    #[default]
+    Hunting,
+}
+
+impl Alertness {
+    /// as in "orc (asleep)"
+    pub fn adjective(self) -> &'static str {
+        match self {
+            Alertness::Asleep => "asleep",
+            Alertness::Wandering => "unaware",
+            Alertness::Alerted { .. } => "alerted",
+            Alertness::Hunting => "hunting",
+        }
+    }
+}
+
+/// A sound made somewhere on the level, heard up to `loudness` tiles away
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Noise {
+    pub x: i32,
+    pub y: i32,
+    pub loudness: i32,
+}
+
+/// Make a noise, heard by the monsters once the current action is over
+pub fn make_noise(x: i32, y: i32, loudness: i32, game: &mut Game) {
+    game.noises.push(Noise { x, y, loudness });
+}
+
+/// Let the monsters hear the noises made since the last time. A monster hears
+/// a noise up to its loudness plus the monster's perception away, or half as
+/// far while asleep. Sleeping monsters wake up, and those that don't know
+/// about the player yet, or lost them, go and see what it was.
+pub fn hear_noises(objects: &mut [Object], game: &mut Game) {
+    let noises: Vec<_> = game.noises.drain(..).collect();
+    for noise in noises {
+        // sound goes over water, chasms and traps alike
+        let distances = DijkstraMap::with_costs(terrain_cost, &game.map, &[(noise.x, noise.y)],
+                                                Movement::Fly);
+        for monster in objects.iter_mut() {
+            let perception = match (monster.ai.as_ref(), monster.fighter) {
+                (Some(_), Some(fighter)) if monster.alive => fighter.perception,
+                _ => continue,
+            };
+            let alertness = monster.alertness;
+            let mut range = noise.loudness + perception;
+            if alertness == Alertness::Asleep {
+                range /= 2;
+            }
+            let (x, y) = monster.pos();
+            let heard = distances.value(x, y)
+                .is_some_and(|distance| distance <= range * STRAIGHT_COST);
+            if !heard {
+                continue;
+            }
+            if alertness == Alertness::Hunting {
+                // that may be the player it is looking for
+                if let Some(Ai::Searching{ref mut x, ref mut y, ..}) = monster.ai {
+                    *x = noise.x;
+                    *y = noise.y;
+                }
+                continue;
+            }
+            if alertness == Alertness::Asleep && game.fov.is_in_fov(x, y) {
+                game.log.add(format!("The {} wakes up!", monster.name), colors::ORANGE);
+            }
+            monster.alertness = Alertness::Alerted { x: noise.x, y: noise.y };
+        }
+    }
+}
+
+/// Whether the monster notices the player now: it has to be awake, and the
+/// player in its view and within its perception. If you can see it, it can
+/// see you
+pub fn notices_player(monster_id: usize, objects: &[Object], game: &Game) -> bool {
+    let monster = &objects[monster_id];
+    let perception = monster.fighter.map_or(0, |f| f.perception);
+    monster.alertness != Alertness::Asleep &&
+        game.fov.is_in_fov(monster.x, monster.y) &&
+        monster.distance_to(&objects[PLAYER]) <= perception as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{add_hunter, empty_level, free_tile};
+
+    #[test]
+    fn alarms_are_only_heard_around() {
+        let (mut objects, mut game) = empty_level();
+        let alarm = objects[PLAYER].pos();
+        let distances = DijkstraMap::new(&game.map, &[alarm], Movement::Fly);
+        let distance = |(x, y)| distances.value(x, y).unwrap_or(i32::MAX);
+        let near = free_tile(&objects, &game, |x, y| distance((x, y)) == 3 * STRAIGHT_COST);
+        let far = free_tile(&objects, &game, |x, y| {
+            let far = distance((x, y));
+            far > 30 * STRAIGHT_COST && far < i32::MAX
+        });
+        let near = add_hunter("orc", near, &mut objects, &game);
+        let far = add_hunter("orc", far, &mut objects, &game);
+        for &id in &[near, far] {
+            objects[id].alertness = Alertness::Wandering;
+        }
+
+        make_noise(alarm.0, alarm.1, ALARM_NOISE, &mut game);
+        hear_noises(&mut objects, &mut game);
+        assert_eq!(objects[near].alertness, Alertness::Alerted { x: alarm.0, y: alarm.1 });
+        assert_eq!(objects[far].alertness, Alertness::Wandering);
+    }
+}
@@ -0,0 +1,471 @@
+use std::cmp;
+
+use crate::PLAYER;
+use crate::ai::{check_morale, Ai};
+use crate::colors::{self, Color};
+use crate::combat::{resist_damage, roll_attack, Attack, AttackResult, DamageType, Defense, Dice,
+                    Resistances};
+use crate::doors::open_door;
+use crate::game::{Game, MessageLog, Messages};
+use crate::item::Item;
+use crate::map::is_blocked;
+use crate::noise::{make_noise, Alertness, ATTACK_NOISE};
+use crate::scheduler::ActionKind;
+use crate::status::{StatModifiers, StatusEffect, StatusKind};
+use crate::terrain::enter_terrain;
+use crate::traps::trigger_trap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Slot {
+    LeftHand,
+    RightHand,
+    Head,
+}
+
+impl std::fmt::Display for Slot {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Slot::LeftHand => write!(f, "left hand"),
+            Slot::RightHand => write!(f, "right hand"),
+            Slot::Head => write!(f, "head"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+/// An object that can be equipped, yielding bonus
+pub struct Equipment {
+    pub slot: Slot,
+    pub equipped: bool,
+    pub max_hp_bonus: i32,
+    pub power_bonus: i32,
+    pub defense_bonus: i32,
+    pub speed_bonus: i32,
+    /// rolled for the damage of a weapon, see `combat`
+    #[serde(default)]
+    pub damage: Option<Dice>,
+    #[serde(default)]
+    pub resistances: Resistances,
+}
+
+/// How a creature gets around, which decides the terrain it can cross, see
+/// `terrain`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Movement {
+    #[default]
+    Walk,
+    /// through deep water without losing anything
+    Swim,
+    /// over chasms, lava and water alike
+    Fly,
+}
+
+/// combat-related properties and methods for monster, player, NPC).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fighter {
+    pub base_max_hp: i32,
+    pub hp: i32,
+    pub xp: i32,
+    pub base_power: i32,
+    pub base_defense: i32,
+    // energy gained each tick, see `scheduler`
+    pub base_speed: i32,
+    // chances to hit and to be missed, see `combat`
+    #[serde(default)]
+    pub base_accuracy: i32,
+    #[serde(default)]
+    pub base_evasion: i32,
+    // rolled for the damage when fighting without a weapon
+    #[serde(default)]
+    pub damage: Option<Dice>,
+    #[serde(default)]
+    pub damage_type: DamageType,
+    #[serde(default)]
+    pub resistances: Resistances,
+    // hit points regained each turn, unless burning
+    #[serde(default)]
+    pub regeneration: i32,
+    // hit points in percent of the maximum below which a monster flees, 0 to
+    // fight to the death
+    #[serde(default)]
+    pub morale: i32,
+    // how far it sees the player and hears beyond noises, see `noise`
+    #[serde(default)]
+    pub perception: i32,
+    pub on_death: DeathCallback,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DeathCallback {
+    Player,
+    Monster,
+}
+
+impl DeathCallback {
+    fn callback(self, object: &mut Object, game: &mut Game) {
+        use DeathCallback::*;
+        let callback: fn(&mut Object, &mut Game) = match self {
+            Player => player_death,
+            Monster => monster_death,
+        };
+        callback(object, game);
+    }
+}
+
+fn player_death(player: &mut Object, game: &mut Game) {
+    // the game has ended!
+    game.log.add("You died!", colors::WHITE);
+
+    // transform player into a corpse!
+    player.char = '%';
+    player.color = colors::DARK_RED;
+}
+
+fn monster_death(monster: &mut Object, game: &mut Game) {
+    // transform into a corpse. It doesn't block, can be attacked and doesn't
+    // move
+    game.log.add(
+        format!("{} is dead! You gain {} experience points",
+                monster.name, monster.fighter.unwrap().xp), colors::GREEN);
+    monster.char = '%';
+    monster.color = colors::DARK_RED;
+    monster.blocks = false;
+    monster.fighter = None;
+    monster.ai = None;
+    monster.statuses.clear();
+    monster.name = format!("remains of {}", monster.name);
+}
+
+// Generic object: Player, Monster, Item, Stairs
+#[derive(Serialize, Deserialize)]
+pub struct Object {
+    pub name: String,
+    pub blocks: bool,
+    pub alive: bool,
+    pub x: i32,
+    pub y: i32,
+    pub char: char,
+    pub color: Color,
+    pub fighter: Option<Fighter>,
+    pub ai: Option<Ai>,
+    pub item: Option<Item>,
+    pub equipment: Option<Equipment>,
+    pub always_visible: bool,
+    pub level: i32,
+    pub energy: i32,
+    #[serde(default)]
+    pub movement: Movement,
+    /// poison, haste and the like, see `status`
+    #[serde(default)]
+    pub statuses: Vec<StatusEffect>,
+    /// whether a monster knows about the player, see `noise`
+    #[serde(default)]
+    pub alertness: Alertness,
+}
+
+impl Object {
+    pub fn pos(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    pub fn set_pos(&mut self, x: i32, y: i32) {
+        self.x = x;
+        self.y = y;
+    }
+
+    pub fn new(x: i32, y: i32, char: char, name: &str, color: Color, blocks: bool) -> Self {
+        Object {
+            x,
+            y,
+            char,
+            name: name.into(),
+            color,
+            blocks,
+            alive: false,
+            fighter: None,
+            ai: None,
+            item: None,
+            equipment: None,
+            always_visible: false,
+            level: 1,
+            energy: 0,
+            movement: Movement::Walk,
+            statuses: vec![],
+            alertness: Alertness::Hunting,
+        }
+    }
+
+    /// return the distance to another object
+    pub fn distance_to(&self, other: &Object) -> f32 {
+        let dx = other.x - self.x;
+        let dy = other.y - self.y;
+        ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
+    }
+
+    /// the damage actually taken from `damage` of that type, after
+    /// resistances
+    pub fn resisted_damage(&self, damage: i32, kind: DamageType, game: &Game) -> i32 {
+        resist_damage(damage, self.resistances(game).get(kind))
+    }
+
+    pub fn take_damage(&mut self, damage: i32, kind: DamageType, game: &mut Game)
+                       -> Option<i32> {
+        let damage = self.resisted_damage(damage, kind, game);
+        // apply damage if possible
+        if let Some(fighter) = self.fighter.as_mut() {
+            if damage > 0 {
+                fighter.hp -= damage;
+            }
+        }
+        // nobody sleeps through that
+        if damage > 0 && self.ai.is_some() {
+            self.alertness = Alertness::Hunting;
+        }
+        // check for death
+        if let Some(fighter) = self.fighter {
+            if fighter.hp <= 0 {
+                self.alive = false;
+                fighter.on_death.callback(self, game);
+                return Some(fighter.xp);
+            }
+        }
+        // some run away instead
+        if damage > 0 && self.ai.is_some() {
+            check_morale(self, game);
+        }
+        None
+    }
+
+    /// never below 1, however weakened
+    pub fn max_hp(&self, game: &Game) -> i32 {
+        let base_max_hp = self.fighter.map_or(0, |f| f.base_max_hp);
+        let bonus: i32 = self.get_all_equipped(game).iter().map(|e| e.max_hp_bonus).sum();
+        cmp::max(1, base_max_hp + bonus + self.status_modifiers().max_hp)
+    }
+
+    pub fn power(&self, game: &Game) -> i32 {
+        let base_power = self.fighter.map_or(0, |f| f.base_power);
+        let bonus: i32 = self.get_all_equipped(game).iter().map(|e| e.power_bonus).sum();
+        base_power + bonus + self.status_modifiers().power
+    }
+
+    pub fn defense(&self, game: &Game) -> i32 {
+        let base_defense = self.fighter.map_or(0, |f| f.base_defense);
+        let bonus: i32 = self.get_all_equipped(game).iter().map(|e| e.defense_bonus).sum();
+        base_defense + bonus + self.status_modifiers().defense
+    }
+
+    /// energy gained each tick. Never below 1, so everyone gets to act
+    pub fn speed(&self, game: &Game) -> i32 {
+        let base_speed = self.fighter.map_or(0, |f| f.base_speed);
+        let bonus: i32 = self.get_all_equipped(game).iter().map(|e| e.speed_bonus).sum();
+        cmp::max(1, base_speed + bonus + self.status_modifiers().speed)
+    }
+
+    pub fn accuracy(&self) -> i32 {
+        self.fighter.map_or(0, |f| f.base_accuracy)
+    }
+
+    pub fn evasion(&self) -> i32 {
+        self.fighter.map_or(0, |f| f.base_evasion)
+    }
+
+    /// its own resistances, with those of its equipment
+    pub fn resistances(&self, game: &Game) -> Resistances {
+        let own = self.fighter.map_or(Resistances::default(), |f| f.resistances);
+        self.get_all_equipped(game)
+            .iter()
+            .fold(own, |total, equipment| total.combine(equipment.resistances))
+    }
+
+    /// the dice of the weapon in hand, or the creature's own
+    pub fn damage_dice(&self, game: &Game) -> Option<Dice> {
+        self.get_all_equipped(game)
+            .iter()
+            .filter_map(|equipment| equipment.damage)
+            .next()
+            .or_else(|| self.fighter.and_then(|f| f.damage))
+    }
+
+    pub fn has_status(&self, kind: StatusKind) -> bool {
+        self.statuses.iter().any(|status| status.kind == kind)
+    }
+
+    /// what the statuses add to the stats, all together
+    pub fn status_modifiers(&self) -> StatModifiers {
+        let mut total = StatModifiers::default();
+        for status in &self.statuses {
+            let modifiers = status.kind.modifiers(status.strength);
+            total.max_hp += modifiers.max_hp;
+            total.power += modifiers.power;
+            total.defense += modifiers.defense;
+            total.speed += modifiers.speed;
+        }
+        total
+    }
+
+    /// pay the energy cost of an action
+    pub fn spend_energy(&mut self, action: ActionKind) {
+        self.energy -= action.cost();
+    }
+
+    pub fn attack(&mut self, target: &mut Object, game: &mut Game) {
+        let attack = Attack {
+            accuracy: self.accuracy(),
+            power: self.power(game),
+            dice: self.damage_dice(game),
+        };
+        let defense = Defense { evasion: target.evasion(), armor: target.defense(game) };
+        let kind = self.fighter.map_or(DamageType::Physical, |f| f.damage_type);
+        make_noise(target.x, target.y, ATTACK_NOISE, game);
+        let (damage, critical) = match roll_attack(attack, defense, &mut game.rng) {
+            AttackResult::Miss => {
+                game.log.add(format!("{} misses {}.", self.name, target.name), colors::WHITE);
+                return;
+            }
+            AttackResult::Hit { damage } => (damage, false),
+            AttackResult::Critical { damage } => (damage, true),
+        };
+        let taken = target.resisted_damage(damage, kind, game);
+        if taken == 0 {
+            game.log.add(format!("{} attacks {} but it has no effect!",
+                                 self.name, target.name), colors::WHITE);
+            return;
+        } else if critical {
+            game.log.add(format!("{} critically hits {} for {} hit points!",
+                         self.name, target.name, taken), colors::LIGHT_RED);
+        } else {
+            game.log.add(format!("{} attacks {} for {} hit points.",
+                         self.name, target.name, taken), colors::WHITE);
+        }
+        // make the target take some damage
+        if let Some(xp) = target.take_damage(damage, kind, game) {
+            // yield experience to the player
+            self.fighter.as_mut().unwrap().xp += xp;
+        }
+    }
+
+    /// heal by the given amount, without going over the maximum
+    pub fn heal(&mut self, amount: i32, game: &Game) {
+        let max_hp = self.max_hp(game);
+        if let Some(ref mut fighter) = self.fighter {
+            fighter.hp += amount;
+            if fighter.hp > max_hp {
+                fighter.hp = max_hp;
+            }
+        }
+    }
+
+    /// returns the distance fromm some coordinates
+    pub fn distance(&self, x: i32, y: i32) -> f32  {
+        (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
+    }
+
+    /// Equip object and show a message about it
+    pub fn equip (&mut self, log: &mut Messages) {
+        if self.item.is_none() {
+            log.add(format!("Can't equip {:?} because it's not an Item.", self.name),
+                    colors::RED);
+            return
+        };
+        if let Some(ref mut equipment) = self.equipment {
+            if !equipment.equipped {
+                equipment.equipped = true;
+                log.add(format!("Equipped {} on {}.", self.name, equipment.slot),
+                        colors::LIGHT_GREEN);
+            }
+        } else {
+            log.add(format!("Can't equip {:?}. Not an equipment.", self.name),
+                            colors::RED);
+        }
+    }
+
+    /// Dequip object and show a message about it
+    pub fn dequip(&mut self, log: &mut Messages) {
+        if self.item.is_none() {
+            log.add(format!("Can't dequip {:?} because it's not an item.", self.name),
+                    colors::RED);
+            return
+        };
+        if let Some(ref mut equipment) = self.equipment {
+            if equipment.equipped {
+                equipment.equipped = false;
+                log.add(format!("Dequipped {} from {}.", self.name, equipment.slot),
+                                colors::LIGHT_YELLOW);
+            }
+        } else {
+            log.add(format!("Can't dequip {:?}. Not an equipment.", self.name),
+                    colors::RED);
+        }
+    }
+
+    /// returns a list of equipped items
+    pub fn get_all_equipped(&self, game: &Game) -> Vec<Equipment> {
+        if self.name == "player" {
+            game.inventory
+                .iter()
+                .filter(|item| {
+                    item.equipment.is_some_and(|e| e.equipped)
+                })
+                .map(|item| item.equipment.unwrap())
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// Mutably borrow two *separate* elements from the given slice.
+/// Panics when the indexes are equal or out of bounds.
+pub fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
+    assert!(first_index != second_index);
+    let split_at_index = cmp::max(first_index, second_index);
+    let (first_slice, second_slice) = items.split_at_mut(split_at_index);
+    if first_index < second_index {
+        (&mut first_slice[first_index], &mut second_slice[0])
+    } else {
+        (&mut second_slice[0], &mut first_slice[second_index])
+    }
+}
+
+/// "you" for the player, "the orc" for an orc
+pub fn name_of(id: usize, objects: &[Object]) -> String {
+    if id == PLAYER {
+        "you".into()
+    } else {
+        format!("the {}", objects[id].name)
+    }
+}
+
+/// Move the object if it can go there, and return the kind of move it was,
+/// which depends on the terrain
+pub fn move_by(id: usize, dx: i32, dy: i32, game: &mut Game, objects: &mut [Object])
+               -> ActionKind {
+    let (x, y) = objects[id].pos();
+    // walking into a closed door opens it instead
+    if open_door(id, x + dx, y + dy, game) {
+        return ActionKind::Move;
+    }
+    let movement = objects[id].movement;
+    if !is_blocked(x + dx, y + dy, movement, &game.map, objects) {
+        objects[id].set_pos(x + dx, y + dy);
+        enter_terrain(id, objects, game);
+        trigger_trap(id, objects, game);
+        return game.map[(x + dx) as usize][(y + dy) as usize].terrain.move_kind(movement);
+    }
+    ActionKind::Move
+}
+
+pub fn move_towards(id: usize, target_x: i32, target_y: i32, game: &mut Game,
+                    objects: &mut [Object]) -> ActionKind {
+    // vector from this object to the target, and distance
+    let dx = target_x - objects[id].x;
+    let dy = target_y - objects[id].y;
+    let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
+
+    // normalize it to length 1 (preserving the direction), then round it and
+    // convert to integer so the movement is restricted to the map grid
+    let dx = (dx as f32 / distance).round() as i32;
+    let dy = (dy as f32 / distance).round() as i32;
+    move_by(id, dx, dy, game, objects)
+}
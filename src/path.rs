@@ -0,0 +1,186 @@
+//! A* pathfinding over the map. Walls and locked doors can't be crossed and
+//! closed doors are opened on the way, but tiles taken by other creatures
+//! only cost more to walk through: a path around a crowd is preferred when
+//! there is a short enough one, otherwise the path goes through and the
+//! walker waits its turn behind the others. Slow terrain, lava and traps
+//! cost more the same way, and what can be crossed at all depends on how the
+//! walker moves. Monsters know where every trap is, the player only knows the
+//! revealed ones.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::map::{Map, Terrain, Tile};
+use crate::object::{Movement, Object};
+use crate::scheduler::ActionKind;
+
+pub const STRAIGHT_COST: i32 = 10;
+// a bit more than a straight step, so paths don't zigzag for nothing
+pub const DIAGONAL_COST: i32 = 11;
+/// extra cost of a tile taken by a creature
+const CROWD_COST: i32 = 50;
+/// extra cost of a known trap or of lava, high enough to go a long way
+/// around
+const DANGER_COST: i32 = 200;
+/// extra cost of deep water for walkers, who would drop what they hold
+const DEEP_WATER_COST: i32 = 50;
+
+pub const DIRECTIONS: [(i32, i32); 8] = [
+    (-1, -1), (0, -1), (1, -1),
+    (-1, 0), (1, 0),
+    (-1, 1), (0, 1), (1, 1),
+];
+
+/// an entry of the open list. Ordered so the `BinaryHeap` pops the lowest
+/// cost first, and ties always break the same way to keep games replayable
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Node {
+    priority: i32,
+    index: usize,
+}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Node) -> Ordering {
+        other.priority.cmp(&self.priority).then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Extra cost of stepping on the tile for a monster moving that way, on top
+/// of the step itself, or None if it can't go there
+pub fn tile_cost(tile: &Tile, movement: Movement) -> Option<i32> {
+    let cost = terrain_cost(tile, movement)?;
+    Some(if tile.trap.is_some() { cost + DANGER_COST } else { cost })
+}
+
+/// the cost of stepping on a tile, such as `tile_cost`
+pub type TileCost = fn(&Tile, Movement) -> Option<i32>;
+
+/// `tile_cost` for the player travelling on their own, who never steps on
+/// terrain that would hurt them and only knows of the revealed traps. They
+/// can still walk there themselves
+pub fn travel_cost(tile: &Tile, movement: Movement) -> Option<i32> {
+    if tile.terrain.harms(movement) {
+        return None;
+    }
+    let cost = terrain_cost(tile, movement)?;
+    Some(if tile.known_trap() { cost + DANGER_COST } else { cost })
+}
+
+/// the part of the cost that comes from the terrain, with no trap on it
+pub fn terrain_cost(tile: &Tile, movement: Movement) -> Option<i32> {
+    if !tile.passable() || !tile.terrain.can_cross(movement) {
+        return None;
+    }
+    // slow terrain costs as much more as it takes more time
+    let move_cost = ActionKind::Move.cost();
+    let mut cost = STRAIGHT_COST * (tile.terrain.move_kind(movement).cost() - move_cost) /
+        move_cost;
+    match (tile.terrain, movement) {
+        (Terrain::Lava, Movement::Walk) | (Terrain::Lava, Movement::Swim) => cost += DANGER_COST,
+        (Terrain::DeepWater, Movement::Walk) => cost += DEEP_WATER_COST,
+        _ => {}
+    }
+    Some(cost)
+}
+
+/// Returns the tiles to walk through to go from `from` to `to`, not counting
+/// `from`, or None if there is no way for a creature moving that way. The
+/// goal itself may be taken, as when chasing someone.
+pub fn find_path(from: (i32, i32), to: (i32, i32), movement: Movement, map: &Map,
+                 objects: &[Object]) -> Option<Vec<(i32, i32)>> {
+    find_path_with_costs(tile_cost, from, to, movement, map, objects)
+}
+
+/// `find_path` with other costs for the tiles, such as `travel_cost`
+pub fn find_path_with_costs(cost: TileCost, from: (i32, i32), to: (i32, i32),
+                            movement: Movement, map: &Map, objects: &[Object])
+                            -> Option<Vec<(i32, i32)>> {
+    let width = map.len() as i32;
+    let height = map.first().map_or(0, |column| column.len()) as i32;
+    let inside = |(x, y): (i32, i32)| x >= 0 && y >= 0 && x < width && y < height;
+    if !inside(from) || !inside(to) ||
+        cost(&map[to.0 as usize][to.1 as usize], movement).is_none() {
+        return None;
+    }
+    let index = |(x, y): (i32, i32)| (x * height + y) as usize;
+    let position = |index: usize| (index as i32 / height, index as i32 % height);
+    let size = (width * height) as usize;
+    let (start, goal) = (index(from), index(to));
+
+    let mut crowded = vec![false; size];
+    for object in objects {
+        if object.blocks && inside(object.pos()) {
+            crowded[index(object.pos())] = true;
+        }
+    }
+
+    let mut cost_so_far = vec![i32::MAX; size];
+    let mut came_from = vec![start; size];
+    let mut closed = vec![false; size];
+    let mut open = BinaryHeap::new();
+    cost_so_far[start] = 0;
+    open.push(Node { priority: heuristic(from, to), index: start });
+
+    while let Some(Node { index: current, .. }) = open.pop() {
+        if current == goal {
+            // walk back from the goal
+            let mut path = vec![];
+            let mut tile = goal;
+            while tile != start {
+                path.push(position(tile));
+                tile = came_from[tile];
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if closed[current] {
+            continue;
+        }
+        closed[current] = true;
+
+        let (x, y) = position(current);
+        for &(dx, dy) in DIRECTIONS.iter() {
+            let next_pos = (x + dx, y + dy);
+            if !inside(next_pos) {
+                continue;
+            }
+            let tile_cost = match cost(&map[next_pos.0 as usize][next_pos.1 as usize],
+                                       movement) {
+                Some(tile_cost) => tile_cost,
+                None => continue,
+            };
+            let next = index(next_pos);
+            let mut cost = cost_so_far[current] + tile_cost +
+                if dx != 0 && dy != 0 { DIAGONAL_COST } else { STRAIGHT_COST };
+            if crowded[next] && next != goal {
+                cost += CROWD_COST;
+            }
+            if cost < cost_so_far[next] {
+                cost_so_far[next] = cost;
+                came_from[next] = current;
+                open.push(Node { priority: cost + heuristic(next_pos, to), index: next });
+            }
+        }
+    }
+    None
+}
+
+/// the first tile of the path from `from` to `to`
+pub fn next_step(from: (i32, i32), to: (i32, i32), movement: Movement, map: &Map,
+                 objects: &[Object]) -> Option<(i32, i32)> {
+    find_path(from, to, movement, map, objects).and_then(|path| path.first().cloned())
+}
+
+/// cost of the best path if nothing was in the way
+fn heuristic((x1, y1): (i32, i32), (x2, y2): (i32, i32)) -> i32 {
+    let dx = (x1 - x2).abs();
+    let dy = (y1 - y2).abs();
+    let (short, long) = if dx < dy { (dx, dy) } else { (dy, dx) };
+    DIAGONAL_COST * short + STRAIGHT_COST * (long - short)
+}
@@ -0,0 +1,97 @@
+//! Things shot across the map, such as arrows and spells. They fly along a
+//! Bresenham line (`fov::line`) and stop at the first wall or creature in the
+//! way, which is the one they hit. Monsters and the player shoot alike.
+
+use crate::colors;
+use crate::combat::{roll_attack, Attack, AttackResult, DamageType, Defense, Dice};
+use crate::fov::line;
+use crate::game::{Game, MessageLog};
+use crate::map::Map;
+use crate::noise::{make_noise, ATTACK_NOISE};
+use crate::object::Object;
+
+/// What is shot, and the damage it does on a hit
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Projectile {
+    pub name: String,
+    pub damage: Dice,
+    pub damage_type: DamageType,
+}
+
+/// Where a projectile stops
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Impact {
+    Creature(usize),
+    Wall(i32, i32),
+    /// it got to the end of its way without hitting anything
+    Nothing,
+}
+
+/// what a projectile from `from` aimed at `to` hits first, going no farther
+/// than `to`
+pub fn trace(from: (i32, i32), to: (i32, i32), map: &Map, objects: &[Object]) -> Impact {
+    for (x, y) in line(from, to) {
+        let creature = objects.iter().position(|object| {
+            object.pos() == (x, y) && object.fighter.is_some() && object.alive
+        });
+        if let Some(id) = creature {
+            return Impact::Creature(id);
+        }
+        if map[x as usize][y as usize].blocked {
+            return Impact::Wall(x, y);
+        }
+    }
+    Impact::Nothing
+}
+
+/// whether a shot from `from` would hit the creature `target_id`, with
+/// nothing in the way
+pub fn line_of_fire(from: (i32, i32), target_id: usize, map: &Map, objects: &[Object]) -> bool {
+    trace(from, objects[target_id].pos(), map, objects) == Impact::Creature(target_id)
+}
+
+/// Shoot at a tile. The first creature on the way gets an attack roll with
+/// the shooter's accuracy and the projectile's damage.
+pub fn shoot(shooter_id: usize, target: (i32, i32), projectile: &Projectile,
+             objects: &mut [Object], game: &mut Game) {
+    let from = objects[shooter_id].pos();
+    make_noise(from.0, from.1, ATTACK_NOISE, game);
+    let id = match trace(from, target, &game.map, objects) {
+        Impact::Creature(id) => id,
+        Impact::Wall(..) | Impact::Nothing => {
+            game.log.add(format!("{}'s {} misses.", objects[shooter_id].name, projectile.name),
+                         colors::WHITE);
+            return;
+        }
+    };
+    let attack = Attack {
+        accuracy: objects[shooter_id].accuracy(),
+        power: 0,
+        dice: Some(projectile.damage),
+    };
+    let defense = Defense { evasion: objects[id].evasion(), armor: objects[id].defense(game) };
+    let (damage, critical) = match roll_attack(attack, defense, &mut game.rng) {
+        AttackResult::Miss => {
+            game.log.add(format!("{}'s {} misses {}.", objects[shooter_id].name,
+                                 projectile.name, objects[id].name),
+                         colors::WHITE);
+            return;
+        }
+        AttackResult::Hit { damage } => (damage, false),
+        AttackResult::Critical { damage } => (damage, true),
+    };
+    let taken = objects[id].resisted_damage(damage, projectile.damage_type, game);
+    let (how, color) = if critical {
+        ("critically hits", colors::LIGHT_RED)
+    } else {
+        ("hits", colors::WHITE)
+    };
+    game.log.add(format!("{}'s {} {} {} for {} hit points.", objects[shooter_id].name,
+                         projectile.name, how, objects[id].name, taken),
+                 color);
+    if let Some(xp) = objects[id].take_damage(damage, projectile.damage_type, game) {
+        if let Some(fighter) = objects[shooter_id].fighter.as_mut() {
+            fighter.xp += xp;
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Recording and replaying whole sessions. A replay file holds the seed of
+//! the game on its first line, followed by one `PlayerAction` per line in
+//! the order they were played. Actions are appended as the game goes, so the
+//! file is complete even if the game crashes.
+
+use std::io::{BufRead, BufReader, Write};
+use std::fs::File;
+use std::error::Error;
+
+use crate::data::Catalog;
+use crate::game::{new_game, play_turn, Game, PlayerAction};
+use crate::object::Object;
+
+pub const REPLAY_FILE: &str = "replay";
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    seed: u64,
+}
+
+pub struct Replay {
+    pub seed: u64,
+    pub actions: Vec<PlayerAction>,
+}
+
+impl Replay {
+    pub fn load(path: &str) -> Result<Replay, Box<dyn Error>> {
+        let file = File::open(path)?;
+        let mut lines = BufReader::new(file).lines();
+        let header = match lines.next() {
+            Some(line) => serde_json::from_str::<Header>(&line?)
+                .map_err(|e| format!("line 1: {}", e))?,
+            None => return Err("the replay file is empty".into()),
+        };
+        let mut actions = vec![];
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let action = serde_json::from_str(&line)
+                .map_err(|e| format!("line {}: {}", index + 2, e))?;
+            actions.push(action);
+        }
+        Ok(Replay { seed: header.seed, actions })
+    }
+}
+
+/// Writes every action of a game to a replay file as it is played
+pub struct Recorder {
+    file: File,
+}
+
+impl Recorder {
+    /// Start a replay file for the game. Actions already played (in a loaded
+    /// game) are written first, so the file always starts from the seed.
+    pub fn create(path: &str, game: &Game) -> Result<Recorder, Box<dyn Error>> {
+        let mut recorder = Recorder { file: File::create(path)? };
+        recorder.write_line(&serde_json::to_string(&Header { seed: game.seed })?)?;
+        for action in &game.history {
+            recorder.record(*action)?;
+        }
+        Ok(recorder)
+    }
+
+    pub fn record(&mut self, action: PlayerAction) -> Result<(), Box<dyn Error>> {
+        let line = serde_json::to_string(&action)?;
+        self.write_line(&line)
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), Box<dyn Error>> {
+        writeln!(self.file, "{}", line)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Play a whole replay without any rendering and return the final state.
+/// The data files must be the same as when it was recorded.
+pub fn play_replay(replay: &Replay, catalog: Catalog) -> (Vec<Object>, Game) {
+    let (mut objects, mut game) = new_game(replay.seed, catalog);
+    for action in &replay.actions {
+        play_turn(*action, &mut objects, &mut game);
+    }
+    (objects, game)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    use crate::PLAYER;
+    use crate::dijkstra::DijkstraMap;
+    use crate::game::{can_level_up, load_game, save_game, Stat};
+    use crate::map::STAIRS_DOWN;
+    use crate::path::DIRECTIONS;
+    use crate::rng::GameRng;
+
+    /// Some action the player could take, chosen by `rng`. It heads down the
+    /// dungeon most of the time, to meet all sorts of things
+    fn random_action(objects: &[Object], game: &Game, rng: &mut GameRng) -> PlayerAction {
+        if can_level_up(objects) {
+            return PlayerAction::LevelUp(Stat::Vitality);
+        }
+        let (x, y) = objects[PLAYER].pos();
+        let stairs = objects.iter()
+            .find(|object| object.name == STAIRS_DOWN)
+            .map(|object| object.pos());
+        if stairs == Some((x, y)) {
+            return PlayerAction::TakeStairs;
+        }
+        let downstairs = stairs.and_then(|stairs| {
+            DijkstraMap::new(&game.map, &[stairs], objects[PLAYER].movement).downhill(x, y)
+        });
+        let (dx, dy) = match downstairs {
+            Some((step_x, step_y)) if rng.gen_range(0, 3) > 0 => (step_x - x, step_y - y),
+            _ => DIRECTIONS[rng.gen_range(0, DIRECTIONS.len())],
+        };
+        match rng.gen_range(0, 20) {
+            0 => PlayerAction::PickUp,
+            1 => PlayerAction::Search,
+            2 => PlayerAction::Wait,
+            3 => PlayerAction::Run { dx, dy },
+            4 if !game.inventory.is_empty() => {
+                let inventory_id = rng.gen_range(0, game.inventory.len());
+                PlayerAction::UseItem { inventory_id, target: None }
+            }
+            _ => PlayerAction::Move { dx, dy },
+        }
+    }
+
+    /// play up to `turns` random actions, recording them, until the player dies
+    fn play(turns: u32, objects: &mut Vec<Object>, game: &mut Game, recorder: &mut Recorder,
+            rng: &mut GameRng) {
+        for _ in 0..turns {
+            if !objects[PLAYER].alive {
+                return;
+            }
+            let action = random_action(objects, game, rng);
+            play_turn(action, objects, game);
+            recorder.record(action).unwrap();
+        }
+    }
+
+    fn state(objects: &[Object], game: &Game) -> String {
+        serde_json::to_string(&(objects, game)).unwrap()
+    }
+
+    #[test]
+    fn replays_and_saved_games_play_the_same() {
+        let catalog = Catalog::load().unwrap();
+        let dir = std::env::temp_dir();
+        let name = |file: &str| {
+            dir.join(format!("bibz_rouge_{}_{}", std::process::id(), file))
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+        let (replay_path, save_path) = (name("replay"), name("savegame"));
+
+        let mut rng = GameRng::new(30);
+        let (mut objects, mut game) = new_game(211, catalog.clone());
+        let mut recorder = Recorder::create(&replay_path, &game).unwrap();
+        play(50, &mut objects, &mut game, &mut recorder, &mut rng);
+
+        // the rest of the game is played after saving and loading it, and
+        // recorded from there
+        save_game(&save_path, &objects, &game).unwrap();
+        let (mut objects, mut game) = load_game(&save_path, catalog.clone()).unwrap();
+        let mut recorder = Recorder::create(&replay_path, &game).unwrap();
+        play(500, &mut objects, &mut game, &mut recorder, &mut rng);
+
+        let replay = Replay::load(&replay_path).unwrap();
+        assert_eq!(replay.seed, 211);
+        assert_eq!(replay.actions, game.history);
+        let (replayed_objects, replayed_game) = play_replay(&replay, catalog);
+        // not `assert_eq`, the whole game would be printed
+        assert!(state(&replayed_objects, &replayed_game) == state(&objects, &game),
+                "the replay doesn't end where the game did");
+
+        std::fs::remove_file(replay_path).unwrap();
+        std::fs::remove_file(save_path).unwrap();
+    }
+}
@@ -0,0 +1,41 @@
+//! The one random number generator used by the game. It is seeded once per
+//! game and saved along with it, so the same seed and the same inputs always
+//! give the same dungeon and the same fights.
+
+use rand::{self, Rng};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameRng {
+    state: u64,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        // scramble the seed (splitmix64) so that close seeds give unrelated
+        // sequences, xorshift can't work from an all zero state
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        GameRng { state: if z == 0 { 0x9E37_79B9_7F4A_7C15 } else { z } }
+    }
+}
+
+impl Rng for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    // xorshift64*
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+/// a fresh seed for games where the player didn't pick one
+pub fn random_seed() -> u64 {
+    rand::thread_rng().gen()
+}
@@ -0,0 +1,72 @@
+//! Energy based turn scheduling. Every tick each fighter gains energy equal
+//! to its speed, and anyone with at least `TURN_ENERGY` can act. Acting costs
+//! energy depending on what was done, so fast creatures act more often and
+//! cheap actions come around sooner.
+
+use crate::PLAYER;
+use crate::ai::ai_take_turn;
+use crate::dijkstra::ChaseMaps;
+use crate::game::Game;
+use crate::noise::hear_noises;
+use crate::object::Object;
+
+/// energy needed before an actor can take an action
+pub const TURN_ENERGY: i32 = 100;
+/// energy gained per tick by an actor of normal speed
+pub const NORMAL_SPEED: i32 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ActionKind {
+    Move,
+    /// a step taken running, faster but loud
+    Run,
+    /// moving through water
+    Wade,
+    /// moving over rubble
+    Climb,
+    Attack,
+    Wait,
+    DrinkPotion,
+    ReadScroll,
+}
+
+impl ActionKind {
+    /// how much energy the action takes
+    pub fn cost(self) -> i32 {
+        use ActionKind::*;
+        match self {
+            Move => 100,
+            Run => 60,
+            Wade => 150,
+            Climb => 200,
+            Attack => 100,
+            Wait => 100,
+            DrinkPotion => 50,
+            ReadScroll => 150,
+        }
+    }
+}
+
+/// Let time pass until the player has enough energy to act again, letting
+/// the monsters act whenever they can.
+pub fn run_until_player_turn(objects: &mut [Object], game: &mut Game) {
+    // the player stays put until then, so the same maps do for every monster
+    game.chase = ChaseMaps::new(&game.map, objects[PLAYER].pos());
+    // what the player just did may have been heard
+    hear_noises(objects, game);
+    while objects[PLAYER].alive && objects[PLAYER].energy < TURN_ENERGY {
+        for object in objects.iter_mut() {
+            if object.fighter.is_some() {
+                let speed = object.speed(game);
+                object.energy += speed;
+            }
+        }
+        for id in 0..objects.len() {
+            while objects[id].ai.is_some() && objects[id].energy >= TURN_ENERGY
+                && objects[PLAYER].alive {
+                ai_take_turn(id, game, objects);
+                hear_noises(objects, game);
+            }
+        }
+    }
+}
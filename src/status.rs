@@ -0,0 +1,259 @@
+//! Status effects: timed conditions on a creature, such as poison or haste.
+//! Each one lasts some of the creature's turns, may do something at the end
+//! of every turn, and may change its stats while it lasts.
+
+use crate::PLAYER;
+use crate::colors::{self, Color};
+use crate::combat::DamageType;
+use crate::game::{Game, MessageLog};
+use crate::map::Terrain;
+use crate::object::{name_of, Object};
+use crate::scheduler::NORMAL_SPEED;
+
+/// hit points lost each turn on fire
+const BURN_DAMAGE: i32 = 2;
+/// hit points regained each turn while regenerating
+const REGENERATION_HEAL: i32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StatusKind {
+    /// moves in random directions
+    Confusion,
+    /// loses hit points every turn, as many as the poison is strong
+    Poison,
+    /// loses hit points every turn, until it ends or the creature gets into
+    /// water
+    Burning,
+    /// regains hit points every turn
+    Regeneration,
+    Haste,
+    Slow,
+    /// loses its turns
+    Stun,
+    /// hits harder and guards worse
+    Rage,
+    /// runs away from the player
+    Fear,
+}
+
+/// What happens when a creature gets a status it already has
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stacking {
+    /// the longer of the two durations is kept
+    Refresh,
+    /// the strengths add up, and the longer duration is kept
+    Intensify,
+}
+
+/// Changes to a creature's stats, added to those of its equipment. None of
+/// the statuses changes the maximum hit points yet, but `max_hp` counts it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StatModifiers {
+    pub max_hp: i32,
+    pub power: i32,
+    pub defense: i32,
+    pub speed: i32,
+}
+
+impl StatusKind {
+    /// as in "you are ..." and "no longer ..."
+    pub fn adjective(self) -> &'static str {
+        use StatusKind::*;
+        match self {
+            Confusion => "confused",
+            Poison => "poisoned",
+            Burning => "on fire",
+            Regeneration => "regenerating",
+            Haste => "hasted",
+            Slow => "slowed",
+            Stun => "stunned",
+            Rage => "enraged",
+            Fear => "frightened",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        use StatusKind::*;
+        match self {
+            Confusion => "Confused",
+            Poison => "Poisoned",
+            Burning => "Burning",
+            Regeneration => "Regenerating",
+            Haste => "Hasted",
+            Slow => "Slowed",
+            Stun => "Stunned",
+            Rage => "Enraged",
+            Fear => "Frightened",
+        }
+    }
+
+    pub fn stacking(self) -> Stacking {
+        match self {
+            StatusKind::Poison => Stacking::Intensify,
+            _ => Stacking::Refresh,
+        }
+    }
+
+    /// the status this one cancels out when added
+    fn opposite(self) -> Option<StatusKind> {
+        match self {
+            StatusKind::Haste => Some(StatusKind::Slow),
+            StatusKind::Slow => Some(StatusKind::Haste),
+            _ => None,
+        }
+    }
+
+    pub fn modifiers(self, strength: i32) -> StatModifiers {
+        use StatusKind::*;
+        match self {
+            // the stronger the poison, the weaker the blows
+            Poison => StatModifiers { power: -strength, ..Default::default() },
+            Burning => StatModifiers { defense: -1, ..Default::default() },
+            Haste => StatModifiers { speed: NORMAL_SPEED / 2, ..Default::default() },
+            Slow => StatModifiers { speed: -NORMAL_SPEED / 2, ..Default::default() },
+            Stun => StatModifiers { defense: -2, ..Default::default() },
+            Rage => StatModifiers { power: 2, defense: -1, ..Default::default() },
+            Confusion | Regeneration | Fear => StatModifiers::default(),
+        }
+    }
+
+    /// the damage the status does, which creatures immune to it don't get it
+    pub fn damage_type(self) -> Option<DamageType> {
+        match self {
+            StatusKind::Poison => Some(DamageType::Poison),
+            StatusKind::Burning => Some(DamageType::Fire),
+            _ => None,
+        }
+    }
+
+    fn color(self) -> Color {
+        use StatusKind::*;
+        match self {
+            Confusion | Stun => colors::LIGHT_GREEN,
+            Poison => colors::GREEN,
+            Burning => colors::ORANGE,
+            Regeneration | Haste => colors::LIGHT_VIOLET,
+            Slow => colors::LIGHT_BLUE,
+            Rage => colors::LIGHT_RED,
+            Fear => colors::LIGHT_YELLOW,
+        }
+    }
+}
+
+/// A status on a creature, see `Object::statuses`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    /// turns left
+    pub turns: i32,
+    pub strength: i32,
+}
+
+/// Give a status to a creature for some turns, following its stacking rule
+pub fn add_status(id: usize, kind: StatusKind, turns: i32, strength: i32,
+                  objects: &mut [Object], game: &mut Game) {
+    if objects[id].fighter.is_none() || turns <= 0 {
+        return;
+    }
+    let immune = kind.damage_type()
+        .is_some_and(|damage_type| objects[id].resistances(game).get(damage_type) >= 100);
+    if immune {
+        return;
+    }
+    if let Some(opposite) = kind.opposite() {
+        if objects[id].has_status(opposite) {
+            objects[id].statuses.retain(|status| status.kind != opposite);
+            status_message(id, &format!("no longer {}", opposite.adjective()), kind.color(),
+                           objects, game);
+            return;
+        }
+    }
+
+    let existing = objects[id].statuses.iter().position(|status| status.kind == kind);
+    match existing {
+        Some(index) => {
+            let status = &mut objects[id].statuses[index];
+            status.turns = status.turns.max(turns);
+            if kind.stacking() == Stacking::Intensify {
+                status.strength += strength;
+            }
+        }
+        None => {
+            let status = StatusEffect { kind, turns, strength };
+            objects[id].statuses.push(status);
+        }
+    }
+    status_message(id, kind.adjective(), kind.color(), objects, game);
+}
+
+/// Run what the statuses do at the end of a turn of the creature, and take
+/// off the ones that ran out. Creatures that regenerate by nature do it here
+/// too, unless they are burning
+pub fn tick_statuses(id: usize, objects: &mut [Object], game: &mut Game) {
+    let (x, y) = objects[id].pos();
+    let terrain = game.map[x as usize][y as usize].terrain;
+    if objects[id].has_status(StatusKind::Burning) &&
+        (terrain == Terrain::ShallowWater || terrain == Terrain::DeepWater) {
+        objects[id].statuses.retain(|status| status.kind != StatusKind::Burning);
+        status_message(id, "no longer on fire", colors::LIGHT_BLUE, objects, game);
+    }
+
+    for index in 0..objects[id].statuses.len() {
+        if !objects[id].alive {
+            return;
+        }
+        let status = objects[id].statuses[index];
+        match status.kind {
+            StatusKind::Poison => {
+                hurt(id, status.strength, DamageType::Poison, "The poison hurts", objects, game);
+            }
+            StatusKind::Burning => {
+                hurt(id, BURN_DAMAGE, DamageType::Fire, "The flames burn", objects, game);
+            }
+            StatusKind::Regeneration => objects[id].heal(REGENERATION_HEAL, game),
+            _ => {}
+        }
+        // dead monsters have no statuses left
+        if !objects[id].alive {
+            return;
+        }
+        objects[id].statuses[index].turns -= 1;
+    }
+
+    let regeneration = objects[id].fighter.map_or(0, |f| f.regeneration);
+    if regeneration > 0 && objects[id].alive && !objects[id].has_status(StatusKind::Burning) {
+        objects[id].heal(regeneration, game);
+    }
+
+    let ended: Vec<_> = objects[id].statuses.iter()
+        .filter(|status| status.turns <= 0)
+        .map(|status| status.kind)
+        .collect();
+    objects[id].statuses.retain(|status| status.turns > 0);
+    for kind in ended {
+        status_message(id, &format!("no longer {}", kind.adjective()), colors::RED,
+                       objects, game);
+    }
+}
+
+fn hurt(id: usize, damage: i32, kind: DamageType, cause: &str, objects: &mut [Object],
+        game: &mut Game) {
+    let (x, y) = objects[id].pos();
+    let taken = objects[id].resisted_damage(damage, kind, game);
+    if taken > 0 && (id == PLAYER || game.fov.is_in_fov(x, y)) {
+        game.log.add(format!("{} {} for {} hit points.", cause, name_of(id, objects), taken),
+                     colors::ORANGE);
+    }
+    objects[id].take_damage(damage, kind, game);
+}
+
+/// "You are poisoned!" for the player, "The orc is poisoned!" for a monster
+/// in view
+fn status_message(id: usize, state: &str, color: Color, objects: &[Object], game: &mut Game) {
+    let (x, y) = objects[id].pos();
+    if id == PLAYER {
+        game.log.add(format!("You are {}!", state), color);
+    } else if game.fov.is_in_fov(x, y) {
+        game.log.add(format!("The {} is {}!", objects[id].name, state), color);
+    }
+}
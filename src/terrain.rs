@@ -0,0 +1,204 @@
+//! Terrain other than walls and floor, and what it does to the creatures
+//! crossing it: walkers wade slowly through water and lose hold of their
+//! things in deep water, lava burns all but fliers, only fliers cross chasms,
+//! and rubble is slow to climb. Patches of it are spread over the levels as
+//! set in `dungeon.json`.
+
+use rand::Rng;
+
+use crate::PLAYER;
+use crate::colors;
+use crate::combat::DamageType;
+use crate::data::Catalog;
+use crate::game::{Game, MessageLog};
+use crate::item::drop_item;
+use crate::map::{from_dungeon_level, Map, Terrain, Tile, Transition, MAP_HEIGHT, MAP_WIDTH};
+use crate::object::{name_of, Movement, Object, Slot};
+use crate::path::DIRECTIONS;
+use crate::rng::GameRng;
+use crate::status::{add_status, StatusKind};
+
+const LAVA_DAMAGE: i32 = 3;
+/// how long the lava keeps burning afterwards
+const LAVA_BURN_TURNS: i32 = 3;
+const MIN_PATCH_RADIUS: i32 = 1;
+const MAX_PATCH_RADIUS: i32 = 3;
+/// places tried for each patch before giving up on it
+const PATCH_TRIES: u32 = 20;
+/// chance in percent for each tile of a rubble patch to get rubble
+const RUBBLE_DENSITY: u32 = 50;
+
+/// Patches of a terrain to spread over the levels
+#[derive(Clone, Debug)]
+pub struct TerrainPatch {
+    pub terrain: Terrain,
+    /// maximum number of patches per level, by dungeon level
+    pub max_patches: Vec<Transition>,
+}
+
+/// what the terrain does to the object that just stepped on it
+pub fn enter_terrain(id: usize, objects: &mut [Object], game: &mut Game) {
+    let (x, y) = objects[id].pos();
+    let terrain = game.map[x as usize][y as usize].terrain;
+    if terrain == Terrain::Lava && objects[id].movement != Movement::Fly {
+        let taken = objects[id].resisted_damage(LAVA_DAMAGE, DamageType::Fire, game);
+        if taken > 0 && (id == PLAYER || game.fov.is_in_fov(x, y)) {
+            game.log.add(format!("The lava burns {} for {} hit points.",
+                                 name_of(id, objects), taken),
+                         colors::ORANGE);
+        }
+        if objects[id].take_damage(LAVA_DAMAGE, DamageType::Fire, game).is_none() {
+            add_status(id, StatusKind::Burning, LAVA_BURN_TURNS, 1, objects, game);
+        }
+    }
+}
+
+/// The player lets go of what they hold in their hands when walking into
+/// deep water
+pub fn drop_held_items(objects: &mut Vec<Object>, game: &mut Game) {
+    let (x, y) = objects[PLAYER].pos();
+    if game.map[x as usize][y as usize].terrain != Terrain::DeepWater ||
+        objects[PLAYER].movement != Movement::Walk {
+        return;
+    }
+    let held: Vec<_> = game.inventory.iter()
+        .enumerate()
+        .filter(|&(_, item)| {
+            item.equipment.is_some_and(|equipment| {
+                equipment.equipped &&
+                    (equipment.slot == Slot::LeftHand || equipment.slot == Slot::RightHand)
+            })
+        })
+        .map(|(inventory_id, _)| inventory_id)
+        .collect();
+    if held.is_empty() {
+        return;
+    }
+    game.log.add("You can't hold on to your things in deep water!", colors::LIGHT_BLUE);
+    // from the last, so the other indexes stay right
+    for &inventory_id in held.iter().rev() {
+        drop_item(inventory_id, game, objects);
+    }
+}
+
+/// Spread the patches of terrain allowed at this level over plain floor. A
+/// patch of lava or chasm is only kept if the exit and the rest of the level
+/// can still be reached from the start without crossing it.
+pub fn add_terrain(map: &mut Map, level: u32, start: (i32, i32), exit: (i32, i32),
+                   catalog: &Catalog, rng: &mut GameRng) {
+    for patch in &catalog.dungeon.terrain {
+        let max_patches = from_dungeon_level(&patch.max_patches, level);
+        for _ in 0..rng.gen_range(0, max_patches + 1) {
+            for _ in 0..PATCH_TRIES {
+                if add_patch(patch.terrain, map, start, exit, rng) {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn add_patch(terrain: Terrain, map: &mut Map, start: (i32, i32), exit: (i32, i32),
+             rng: &mut GameRng) -> bool {
+    let (center_x, center_y) = (rng.gen_range(1, MAP_WIDTH - 1), rng.gen_range(1, MAP_HEIGHT - 1));
+    if !plain_floor(&map[center_x as usize][center_y as usize]) {
+        return false;
+    }
+    let radius = rng.gen_range(MIN_PATCH_RADIUS, MAX_PATCH_RADIUS + 1);
+    let reachable = safe_reach(map, start);
+    let before = map.clone();
+
+    for x in (center_x - radius)..(center_x + radius + 1) {
+        for y in (center_y - radius)..(center_y + radius + 1) {
+            let distance = (x - center_x).pow(2) + (y - center_y).pow(2);
+            let inside = x >= 0 && y >= 0 && x < MAP_WIDTH && y < MAP_HEIGHT;
+            if !inside || distance > radius * radius || (x, y) == start || (x, y) == exit ||
+                !plain_floor(&map[x as usize][y as usize]) {
+                continue;
+            }
+            let tile_terrain = match terrain {
+                // deep in the middle, shallow around
+                Terrain::DeepWater if distance > (radius - 1) * (radius - 1) => {
+                    Terrain::ShallowWater
+                }
+                Terrain::Rubble if rng.gen_range(0, 100) >= RUBBLE_DENSITY => continue,
+                other => other,
+            };
+            map[x as usize][y as usize].terrain = tile_terrain;
+        }
+    }
+
+    // the exit, and everything that could be reached safely, still can be
+    // from the start, unless it was covered
+    let still_reachable = safe_reach(map, start);
+    if !still_reachable[exit.0 as usize][exit.1 as usize] {
+        *map = before;
+        return false;
+    }
+    for x in 0..MAP_WIDTH as usize {
+        for y in 0..MAP_HEIGHT as usize {
+            if reachable[x][y] && safe(&map[x][y]) && !still_reachable[x][y] {
+                *map = before;
+                return false;
+            }
+        }
+    }
+    true
+}
+
+fn plain_floor(tile: &Tile) -> bool {
+    tile.terrain == Terrain::Floor && !tile.blocked && tile.door.is_none()
+}
+
+/// can be walked on without being burned or falling
+fn safe(tile: &Tile) -> bool {
+    tile.passable() && tile.terrain != Terrain::Lava && tile.terrain != Terrain::Chasm
+}
+
+/// the tiles that can be walked to safely from `(x, y)`
+fn safe_reach(map: &Map, (x, y): (i32, i32)) -> Vec<Vec<bool>> {
+    let mut reached = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut stack = vec![];
+    if safe(&map[x as usize][y as usize]) {
+        reached[x as usize][y as usize] = true;
+        stack.push((x, y));
+    }
+    while let Some((x, y)) = stack.pop() {
+        for &(dx, dy) in DIRECTIONS.iter() {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= MAP_WIDTH || ny >= MAP_HEIGHT {
+                continue;
+            }
+            if safe(&map[nx as usize][ny as usize]) && !reached[nx as usize][ny as usize] {
+                reached[nx as usize][ny as usize] = true;
+                stack.push((nx, ny));
+            }
+        }
+    }
+    reached
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{make_map, STAIRS_DOWN};
+
+    #[test]
+    fn stairs_down_can_be_reached_from_the_start() {
+        let catalog = Catalog::load().unwrap();
+        for level in 1..9 {
+            for seed in 0..50 {
+                let mut rng = GameRng::new(seed);
+                let mut objects = vec![Object::new(0, 0, '@', "player", colors::WHITE, true)];
+                let map = make_map(&mut objects, level, &catalog, &mut rng);
+                let start = objects[PLAYER].pos();
+                let (x, y) = objects.iter()
+                    .find(|object| object.name == STAIRS_DOWN)
+                    .unwrap()
+                    .pos();
+                assert!(safe_reach(&map, start)[x as usize][y as usize],
+                        "level {} seed {}: no way from {:?} to {:?}", level, seed, start, (x, y));
+            }
+        }
+    }
+}
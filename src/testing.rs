@@ -0,0 +1,48 @@
+//! Setup shared by the tests: the first level of a real game with nobody on
+//! it but the player, to put on it what each test needs.
+
+use crate::PLAYER;
+use crate::data::Catalog;
+use crate::game::{new_game, Game};
+use crate::map::{is_blocked, MAP_HEIGHT, MAP_WIDTH};
+use crate::noise::Alertness;
+use crate::object::{Movement, Object};
+
+/// the first level of a game, emptied of everything but the player
+pub fn empty_level() -> (Vec<Object>, Game) {
+    let (mut objects, game) = new_game(1, Catalog::load().unwrap());
+    objects.truncate(1);
+    (objects, game)
+}
+
+/// the first tile walkers can step on, going over the map, that `wanted` is
+/// true for
+pub fn free_tile<F>(objects: &[Object], game: &Game, wanted: F) -> (i32, i32)
+    where F: Fn(i32, i32) -> bool
+{
+    (0..MAP_WIDTH)
+        .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+        .find(|&(x, y)| {
+            wanted(x, y) && !is_blocked(x, y, Movement::Walk, &game.map, objects)
+        })
+        .expect("no such tile on the level")
+}
+
+pub fn next_to_player(objects: &[Object], game: &Game) -> (i32, i32) {
+    let (player_x, player_y) = objects[PLAYER].pos();
+    free_tile(objects, game, |x, y| (x - player_x).abs() <= 1 && (y - player_y).abs() <= 1)
+}
+
+pub fn out_of_view(objects: &[Object], game: &Game) -> (i32, i32) {
+    free_tile(objects, game, |x, y| !game.fov.is_in_fov(x, y))
+}
+
+/// Put the monster from `monsters.json` with that name there, hunting the
+/// player, and return its id
+pub fn add_hunter(name: &str, (x, y): (i32, i32), objects: &mut Vec<Object>, game: &Game)
+                  -> usize {
+    let mut monster = game.catalog.monsters.get(name).unwrap().spawn(x, y);
+    monster.alertness = Alertness::Hunting;
+    objects.push(monster);
+    objects.len() - 1
+}
@@ -0,0 +1,139 @@
+//! Traps hidden in the floor. They go off under whoever steps on them, and
+//! are revealed when the player sees that happen or finds them by searching.
+//! A revealed trap is known to everyone, and paths go around it.
+
+use rand::Rng;
+
+use crate::PLAYER;
+use crate::colors;
+use crate::combat::DamageType;
+use crate::game::{Game, MessageLog};
+use crate::map::{is_blocked, Terrain, Trap, TrapKind, MAP_HEIGHT, MAP_WIDTH};
+use crate::noise::{make_noise, ALARM_NOISE};
+use crate::object::{name_of, Object};
+use crate::status::{add_status, StatusKind};
+
+const DART_DAMAGE: i32 = 2;
+/// how long the poison of a dart lasts
+const DART_POISON_TURNS: i32 = 3;
+const PIT_DAMAGE: i32 = 2;
+/// how far the gas spreads, and how long it confuses
+const GAS_RADIUS: f32 = 2.5;
+const GAS_TURNS: i32 = 5;
+/// random tiles tried to find a free one to teleport to
+const TELEPORT_TRIES: u32 = 100;
+/// how far searching looks, and the chance in percent to find each trap
+const SEARCH_RADIUS: i32 = 2;
+const SEARCH_CHANCE: u32 = 50;
+
+/// Set off the trap the object stands on, if any
+pub fn trigger_trap(id: usize, objects: &mut [Object], game: &mut Game) {
+    let (x, y) = objects[id].pos();
+    let trap = match game.map[x as usize][y as usize].trap {
+        Some(trap) => trap,
+        None => return,
+    };
+    let seen = id == PLAYER || game.fov.is_in_fov(x, y);
+    if seen {
+        game.map[x as usize][y as usize].trap = Some(Trap { kind: trap.kind, revealed: true });
+        if id == PLAYER {
+            game.log.add(format!("You step on a {}!", trap.kind.name()), colors::ORANGE);
+        } else {
+            game.log.add(format!("The {} steps on a {}!", objects[id].name, trap.kind.name()),
+                         colors::ORANGE);
+        }
+    }
+
+    match trap.kind {
+        TrapKind::Dart => {
+            if seen {
+                game.log.add(format!("A poisoned dart hits {} for {} hit points.",
+                                     name_of(id, objects), DART_DAMAGE),
+                             colors::ORANGE);
+            }
+            if objects[id].take_damage(DART_DAMAGE, DamageType::Physical, game).is_none() {
+                add_status(id, StatusKind::Poison, DART_POISON_TURNS, 1, objects, game);
+            }
+        }
+        TrapKind::Teleport => {
+            for _ in 0..TELEPORT_TRIES {
+                let (new_x, new_y) = (game.rng.gen_range(0, MAP_WIDTH),
+                                      game.rng.gen_range(0, MAP_HEIGHT));
+                let tile = game.map[new_x as usize][new_y as usize];
+                if tile.terrain == Terrain::Floor && tile.trap.is_none() &&
+                    !is_blocked(new_x, new_y, objects[id].movement, &game.map, objects) {
+                    objects[id].set_pos(new_x, new_y);
+                    break;
+                }
+            }
+            if id == PLAYER {
+                game.log.add("You are pulled away!", colors::ORANGE);
+            } else if seen {
+                game.log.add(format!("The {} vanishes!", objects[id].name), colors::ORANGE);
+            }
+        }
+        TrapKind::Alarm => {
+            game.log.add("A loud alarm goes off!", colors::ORANGE);
+            make_noise(x, y, ALARM_NOISE, game);
+        }
+        TrapKind::Pit => {
+            // the player goes on falling to the level below, see `fell_in_pit`
+            if id == PLAYER {
+                game.log.add("You fall down the pit!", colors::ORANGE);
+            } else if seen {
+                game.log.add(format!("The {} falls into the pit and climbs out.",
+                                     objects[id].name),
+                             colors::ORANGE);
+            }
+            objects[id].take_damage(PIT_DAMAGE, DamageType::Physical, game);
+        }
+        TrapKind::ConfusionGas => {
+            if seen {
+                game.log.add("A cloud of gas bursts out!", colors::ORANGE);
+            }
+            let confused: Vec<_> = objects.iter()
+                .enumerate()
+                .filter(|&(_, object)| {
+                    object.fighter.is_some() && object.alive && object.distance(x, y) <= GAS_RADIUS
+                })
+                .map(|(id, _)| id)
+                .collect();
+            for id in confused {
+                add_status(id, StatusKind::Confusion, GAS_TURNS, 1, objects, game);
+            }
+        }
+    }
+}
+
+/// whether the player is on a pit, and should drop to the level below
+pub fn fell_in_pit(objects: &[Object], game: &Game) -> bool {
+    let (x, y) = objects[PLAYER].pos();
+    objects[PLAYER].alive &&
+        game.map[x as usize][y as usize].trap.map(|trap| trap.kind) == Some(TrapKind::Pit)
+}
+
+/// look for hidden traps around the player
+pub fn search(objects: &[Object], game: &mut Game) {
+    let (player_x, player_y) = objects[PLAYER].pos();
+    let mut found = false;
+    for x in (player_x - SEARCH_RADIUS)..(player_x + SEARCH_RADIUS + 1) {
+        for y in (player_y - SEARCH_RADIUS)..(player_y + SEARCH_RADIUS + 1) {
+            if x < 0 || y < 0 || x >= MAP_WIDTH || y >= MAP_HEIGHT {
+                continue;
+            }
+            let trap = match game.map[x as usize][y as usize].trap {
+                Some(trap) if !trap.revealed => trap,
+                _ => continue,
+            };
+            if game.rng.gen_range(0, 100) < SEARCH_CHANCE {
+                game.map[x as usize][y as usize].trap = Some(Trap { kind: trap.kind,
+                                                                     revealed: true });
+                game.log.add(format!("You find a {}!", trap.kind.name()), colors::LIGHT_CYAN);
+                found = true;
+            }
+        }
+    }
+    if !found {
+        game.log.add("You find nothing.", colors::LIGHT_GREY);
+    }
+}
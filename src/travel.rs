@@ -0,0 +1,242 @@
+//! The player walking on their own, one turn at a time: auto-explore, and
+//! going to a tile or to the stairs along the same paths as the monsters,
+//! but never over terrain that would hurt the player.
+//! The frontend asks for the next action each frame and plays it like any
+//! other, so travelling ends up in replays as plain moves.
+
+use crate::PLAYER;
+use crate::game::{Game, PlayerAction};
+use crate::item::INVENTORY_SIZE;
+use crate::map::{MAP_HEIGHT, MAP_WIDTH};
+use crate::object::Object;
+use crate::path::{find_path_with_costs, travel_cost};
+use crate::status::StatusKind;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Destination {
+    /// the closest unexplored tile, then the stairs once there is none left
+    Explore,
+    Tile(i32, i32),
+    /// the stairs with that name, once the player has seen them
+    Stairs(&'static str),
+}
+
+/// Why travelling stopped, with what to tell the player if anything. It is
+/// left to the frontend to show, `Game.log` only holds what the turns did
+pub type Stop = Option<String>;
+
+pub struct Travel {
+    destination: Destination,
+    pick_up: bool,
+    // how things were at the previous step, to notice what changed since
+    hp: i32,
+    log_len: usize,
+    picked_up: bool,
+}
+
+impl Travel {
+    /// `pick_up`: walk to the items seen on the way and take them
+    pub fn explore(pick_up: bool, objects: &[Object], game: &Game) -> Travel {
+        Travel::new(Destination::Explore, pick_up, objects, game)
+    }
+
+    /// fails if the tile hasn't been explored or can't be reached
+    pub fn to_tile(x: i32, y: i32, objects: &[Object], game: &Game) -> Result<Travel, Stop> {
+        let explored = x >= 0 && y >= 0 && x < MAP_WIDTH && y < MAP_HEIGHT &&
+            game.map[x as usize][y as usize].explored;
+        if !explored {
+            return Err(None);
+        }
+        if travel_path((x, y), objects, game).is_none() {
+            return Err(Some("You can't get there.".into()));
+        }
+        Ok(Travel::new(Destination::Tile(x, y), false, objects, game))
+    }
+
+    /// `stairs` is `STAIRS_DOWN` or `STAIRS_UP`. Fails if the player hasn't
+    /// found them yet
+    pub fn to_stairs(stairs: &'static str, objects: &[Object], game: &Game)
+                     -> Result<Travel, Stop> {
+        if known_stairs(stairs, objects, game).is_none() {
+            return Err(Some(format!("You haven't found the {} yet.", stairs)));
+        }
+        Ok(Travel::new(Destination::Stairs(stairs), false, objects, game))
+    }
+
+    fn new(destination: Destination, pick_up: bool, objects: &[Object], game: &Game) -> Travel {
+        Travel {
+            destination,
+            pick_up,
+            hp: objects[PLAYER].fighter.map_or(0, |f| f.hp),
+            log_len: game.log.len(),
+            picked_up: false,
+        }
+    }
+
+    /// The action for the next step, or the stop once arrived or if
+    /// something happened that the player should look at.
+    pub fn next_action(&mut self, objects: &[Object], game: &Game)
+                       -> Result<PlayerAction, Stop> {
+        let player = &objects[PLAYER];
+        if !player.alive {
+            return Err(None);
+        }
+        if let Some(monster) = monster_in_view(objects, game) {
+            return Err(Some(format!("You see the {}.", monster.name)));
+        }
+        let hp = player.fighter.map_or(0, |f| f.hp);
+        if hp < self.hp {
+            return Err(None);
+        }
+        // the steps wouldn't go where they should
+        if player.has_status(StatusKind::Confusion) || player.has_status(StatusKind::Stun) {
+            return Err(None);
+        }
+        // the message is left on screen for the player to read, except the
+        // ones from picking up items along the way
+        if game.log.len() > self.log_len && !self.picked_up {
+            return Err(None);
+        }
+        self.hp = hp;
+        self.picked_up = false;
+
+        let action = match self.destination {
+            Destination::Explore => self.explore_step(objects, game),
+            Destination::Tile(x, y) => walk_to(x, y, objects, game),
+            Destination::Stairs(stairs) => {
+                known_stairs(stairs, objects, game)
+                    .ok_or(None)
+                    .and_then(|(x, y)| walk_to(x, y, objects, game))
+            }
+        };
+        self.log_len = game.log.len();
+        action
+    }
+
+    fn explore_step(&mut self, objects: &[Object], game: &Game) -> Result<PlayerAction, Stop> {
+        let (x, y) = objects[PLAYER].pos();
+        if self.pick_up && item_at(x, y, objects) && game.inventory.len() < INVENTORY_SIZE {
+            self.picked_up = true;
+            return Ok(PlayerAction::PickUp);
+        }
+
+        // the closest of the unexplored tiles and the items to pick up
+        let goals = &game.goals;
+        let mut maps = vec![&goals.unexplored];
+        if self.pick_up && game.inventory.len() < INVENTORY_SIZE {
+            maps.push(&goals.items);
+        }
+        let closest = maps.into_iter()
+            .filter_map(|map| map.value(x, y).map(|value| (value, map)))
+            .min_by_key(|&(value, _)| value);
+        if let Some(step) = closest.and_then(|(_, map)| map.downhill(x, y)) {
+            return Ok(step_towards(step, objects));
+        }
+
+        // everything has been seen, head for the stairs down
+        match goals.stairs_down.downhill(x, y) {
+            Some(step) => Ok(step_towards(step, objects)),
+            None => Err(Some("There is nothing left to explore.".into())),
+        }
+    }
+}
+
+/// the next step of the path to the tile, stopping once there
+fn walk_to(x: i32, y: i32, objects: &[Object], game: &Game) -> Result<PlayerAction, Stop> {
+    if objects[PLAYER].pos() == (x, y) {
+        return Err(None);
+    }
+    match travel_path((x, y), objects, game).and_then(|path| path.first().cloned()) {
+        Some(step) => Ok(step_towards(step, objects)),
+        None => Err(Some("You can't get there.".into())),
+    }
+}
+
+/// the player's path to the tile, around what would hurt them
+fn travel_path(to: (i32, i32), objects: &[Object], game: &Game) -> Option<Vec<(i32, i32)>> {
+    let player = &objects[PLAYER];
+    find_path_with_costs(travel_cost, player.pos(), to, player.movement, &game.map, objects)
+}
+
+fn known_stairs(stairs: &str, objects: &[Object], game: &Game) -> Option<(i32, i32)> {
+    objects.iter()
+        .find(|object| {
+            object.name == stairs && game.map[object.x as usize][object.y as usize].explored
+        })
+        .map(|object| object.pos())
+}
+
+/// a monster the player can see, if any
+pub fn monster_in_view<'a>(objects: &'a [Object], game: &Game) -> Option<&'a Object> {
+    objects.iter().enumerate()
+        .find(|&(id, object)| {
+            id != PLAYER && object.fighter.is_some() && object.ai.is_some() &&
+                game.fov.is_in_fov(object.x, object.y)
+        })
+        .map(|(_, object)| object)
+}
+
+fn item_at(x: i32, y: i32, objects: &[Object]) -> bool {
+    objects.iter().any(|object| object.pos() == (x, y) && object.item.is_some())
+}
+
+fn step_towards((x, y): (i32, i32), objects: &[Object]) -> PlayerAction {
+    let (player_x, player_y) = objects[PLAYER].pos();
+    PlayerAction::Move { dx: x - player_x, dy: y - player_y }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::play_turn;
+    use crate::map::{is_blocked, Terrain};
+    use crate::object::Movement;
+    use crate::testing::{empty_level, free_tile};
+
+    /// play the travel to its end, checking that no step hurts the player
+    fn travel_safely(mut travel: Travel, objects: &mut Vec<Object>, game: &mut Game) -> usize {
+        let mut steps = 0;
+        while let Ok(action) = travel.next_action(objects, game) {
+            play_turn(action, objects, game);
+            let (x, y) = objects[PLAYER].pos();
+            let terrain = game.map[x as usize][y as usize].terrain;
+            assert!(!terrain.harms(Movement::Walk), "stepped on {:?}", terrain);
+            steps += 1;
+            assert!(steps < 2000, "still travelling");
+        }
+        steps
+    }
+
+    #[test]
+    fn travel_never_steps_on_lava_or_deep_water() {
+        let (mut objects, mut game) = empty_level();
+        // in the open, with lava and deep water on the straight way to a tile
+        let (x, y) = free_tile(&objects, &game, |x, y| {
+            x > 0 && y > 0 && x + 3 < MAP_WIDTH && y + 1 < MAP_HEIGHT &&
+                (x - 1..=x + 3).all(|x| (y - 1..=y + 1).all(|y| {
+                    !is_blocked(x, y, Movement::Walk, &game.map, &objects)
+                }))
+        });
+        objects[PLAYER].set_pos(x, y);
+        game.map[x as usize + 1][y as usize].terrain = Terrain::Lava;
+        game.map[x as usize + 2][y as usize].terrain = Terrain::DeepWater;
+        for tile in game.map.iter_mut().flatten() {
+            tile.explored = true;
+            tile.trap = None;
+        }
+
+        let travel = Travel::to_tile(x + 3, y, &objects, &game).unwrap();
+        travel_safely(travel, &mut objects, &mut game);
+        assert_eq!(objects[PLAYER].pos(), (x + 3, y));
+        let stop = Travel::to_tile(x + 1, y, &objects, &game).err().unwrap();
+        assert_eq!(stop.as_deref(), Some("You can't get there."));
+
+        // and again with the whole level to explore
+        for tile in game.map.iter_mut().flatten() {
+            tile.explored = false;
+        }
+        play_turn(PlayerAction::Wait, &mut objects, &mut game);
+        let explore = Travel::explore(false, &objects, &game);
+        assert!(travel_safely(explore, &mut objects, &mut game) > 0);
+    }
+}
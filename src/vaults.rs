@@ -0,0 +1,302 @@
+//! Vaults: hand-made rooms drawn as ASCII in `vaults.json` and stamped into
+//! levels, for treasure rooms and lairs random generation can't make.
+//!
+//! In the drawing, `#` is a wall, `.` is floor, `+` is a door, a space leaves
+//! the level as generated, and the other characters are defined by the
+//! vault's legend as a given monster or item standing on floor. The doors of
+//! a locked vault are locked, and a key is left where the player can get it.
+
+use std::collections::HashMap;
+
+use rand::Rng;
+use serde_json::Value;
+
+use crate::PLAYER;
+use crate::data::{Catalog, Fields};
+use crate::dijkstra::DijkstraMap;
+use crate::item::Item;
+use crate::items::ItemDef;
+use crate::map::{from_dungeon_level, Door, Map, Terrain, Tile, Transition, MAP_HEIGHT,
+                 MAP_WIDTH};
+use crate::object::{Movement, Object};
+use crate::rng::GameRng;
+
+/// places tried for each vault before giving up on it
+const PLACEMENT_TRIES: u32 = 50;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cell {
+    /// left as generated
+    Keep,
+    Wall,
+    Floor,
+    Door,
+    /// floor with the named monster on it
+    Monster(String),
+    /// floor with the named item on it
+    Item(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct VaultDef {
+    pub name: String,
+    /// chance in percent of the vault being in a level, by dungeon level
+    pub chance: Vec<Transition>,
+    pub locked: bool,
+    /// `cells[y][x]`, every row as wide as the widest
+    pub cells: Vec<Vec<Cell>>,
+}
+
+impl VaultDef {
+    pub fn width(&self) -> i32 {
+        self.cells.first().map_or(0, |row| row.len()) as i32
+    }
+
+    pub fn height(&self) -> i32 {
+        self.cells.len() as i32
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct VaultTable {
+    pub vaults: Vec<VaultDef>,
+}
+
+pub fn parse_vaults(text: &str) -> Result<VaultTable, String> {
+    let root: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let root = Fields::new("top level", &root, &["vaults"])?;
+    let mut vaults: Vec<VaultDef> = vec![];
+    for (index, value) in root.array("vaults")?.iter().enumerate() {
+        let context = format!("vault {}", index + 1);
+        let vault = parse_vault(&context, value)?;
+        if vaults.iter().any(|other| other.name == vault.name) {
+            return Err(format!("{}: field `name`: \"{}\" is defined twice",
+                               context, vault.name));
+        }
+        vaults.push(vault);
+    }
+    Ok(VaultTable { vaults })
+}
+
+fn parse_vault(context: &str, value: &Value) -> Result<VaultDef, String> {
+    let fields = Fields::new(context, value, &["name", "chance", "locked", "legend", "map"])?;
+    let name = fields.string("name")?;
+    if name.is_empty() {
+        return Err(format!("{}: field `name` is empty", context));
+    }
+    let context = format!("{} ({})", context, name);
+    let fields = fields.with_context(&context);
+
+    let mut legend = HashMap::new();
+    if fields.has("legend") {
+        let legend_context = format!("{}: field `legend`", context);
+        let entries = fields.get("legend")?.as_object()
+            .ok_or_else(|| format!("{}: expected an object", legend_context))?;
+        for (key, value) in entries {
+            let mut chars = key.chars();
+            let glyph = match (chars.next(), chars.next()) {
+                (Some(glyph), None) if !"#.+ ".contains(glyph) => glyph,
+                _ => return Err(format!("{}: `{}` must be a single character other \
+                                         than `#`, `.`, `+` and space",
+                                        legend_context, key)),
+            };
+            let entry_context = format!("{}, `{}`", legend_context, key);
+            let entry = Fields::new(&entry_context, value, &["monster", "item"])?;
+            let cell = match (entry.has("monster"), entry.has("item")) {
+                (true, false) => Cell::Monster(entry.string("monster")?),
+                (false, true) => Cell::Item(entry.string("item")?),
+                _ => return Err(format!("{}: needs either `monster` or `item`",
+                                        entry_context)),
+            };
+            legend.insert(glyph, cell);
+        }
+    }
+
+    let mut cells = vec![];
+    for (y, row) in fields.array("map")?.iter().enumerate() {
+        let row = row.as_str()
+            .ok_or_else(|| format!("{}: field `map`, row {} must be a string", context, y + 1))?;
+        let mut cell_row = vec![];
+        for (x, glyph) in row.chars().enumerate() {
+            let cell = match glyph {
+                ' ' => Cell::Keep,
+                '#' => Cell::Wall,
+                '.' => Cell::Floor,
+                '+' => Cell::Door,
+                other => match legend.get(&other) {
+                    Some(cell) => cell.clone(),
+                    None => return Err(format!("{}: field `map`, row {}, column {}: `{}` is \
+                                                not in the legend", context, y + 1, x + 1,
+                                               other)),
+                },
+            };
+            cell_row.push(cell);
+        }
+        cells.push(cell_row);
+    }
+    let width = cells.iter().map(|row| row.len()).max().unwrap_or(0);
+    if width == 0 {
+        return Err(format!("{}: field `map` is empty", context));
+    }
+    if width as i32 > MAP_WIDTH - 2 || cells.len() as i32 > MAP_HEIGHT - 2 {
+        return Err(format!("{}: field `map` is bigger than the level", context));
+    }
+    for row in cells.iter_mut() {
+        row.resize(width, Cell::Keep);
+    }
+
+    Ok(VaultDef {
+        name,
+        chance: fields.transitions("chance")?,
+        locked: fields.has("locked") && fields.bool("locked")?,
+        cells,
+    })
+}
+
+/// the monsters and items of the vaults must be in the other data files
+pub fn check_vaults(catalog: &Catalog) -> Result<(), String> {
+    for vault in &catalog.vaults.vaults {
+        if vault.locked && key_def(catalog).is_none() {
+            return Err(format!("vault ({}): field `locked`: no item is a key", vault.name));
+        }
+        for cell in vault.cells.iter().flat_map(|row| row.iter()) {
+            let unknown = match *cell {
+                Cell::Monster(ref name) if !catalog.monsters.monsters.iter()
+                    .any(|monster| &monster.name == name) => {
+                    Some(format!("monster \"{}\"", name))
+                }
+                Cell::Item(ref name) if !catalog.items.items.iter()
+                    .any(|item| &item.name == name) => {
+                    Some(format!("item \"{}\"", name))
+                }
+                _ => None,
+            };
+            if let Some(unknown) = unknown {
+                return Err(format!("vault ({}): field `legend`: unknown {}",
+                                   vault.name, unknown));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Roll for every vault allowed at this level and stamp the lucky ones, and
+/// return the tiles they took. A vault only goes over open floor with a
+/// floor border all around, so the level stays connected, and never over the
+/// `keep_clear` tiles. The player must already be on the level, to leave the
+/// keys within reach.
+pub fn place_vaults(map: &mut Map, objects: &mut Vec<Object>, level: u32,
+                    keep_clear: &[(i32, i32)], catalog: &Catalog, rng: &mut GameRng)
+                    -> Vec<(i32, i32)> {
+    let mut taken = vec![];
+    let mut keys = 0;
+    for vault in &catalog.vaults.vaults {
+        let chance = from_dungeon_level(&vault.chance, level);
+        if rng.gen_range(0, 100) >= chance {
+            continue;
+        }
+        for _ in 0..PLACEMENT_TRIES {
+            let x = rng.gen_range(1, MAP_WIDTH - vault.width());
+            let y = rng.gen_range(1, MAP_HEIGHT - vault.height());
+            if fits(vault, x, y, map, objects, keep_clear) {
+                taken.extend(stamp(vault, x, y, map, objects, catalog, rng));
+                if vault.locked {
+                    keys += 1;
+                }
+                break;
+            }
+        }
+    }
+    if keys > 0 {
+        place_keys(keys, map, objects, keep_clear, catalog, rng);
+    }
+    taken
+}
+
+fn key_def(catalog: &Catalog) -> Option<&ItemDef> {
+    catalog.items.items.iter().find(|item| item.item == Item::Key)
+}
+
+/// drop the keys on free floor the player can walk to without a key, which
+/// is never inside a locked vault
+fn place_keys(keys: u32, map: &Map, objects: &mut Vec<Object>, keep_clear: &[(i32, i32)],
+              catalog: &Catalog, rng: &mut GameRng) {
+    let key = match key_def(catalog) {
+        Some(key) => key,
+        None => return,
+    };
+    let reachable = DijkstraMap::new(map, &[objects[PLAYER].pos()], Movement::Walk);
+    let mut free = vec![];
+    for x in 0..MAP_WIDTH {
+        for y in 0..MAP_HEIGHT {
+            let tile = map[x as usize][y as usize];
+            if !tile.blocked && tile.terrain == Terrain::Floor && tile.door.is_none() &&
+                reachable.value(x, y).is_some() &&
+                !keep_clear.contains(&(x, y)) &&
+                !objects.iter().any(|object| object.pos() == (x, y)) {
+                free.push((x, y));
+            }
+        }
+    }
+    for _ in 0..keys {
+        if free.is_empty() {
+            break;
+        }
+        let (x, y) = free.swap_remove(rng.gen_range(0, free.len()));
+        objects.push(key.spawn(x, y));
+    }
+}
+
+fn fits(vault: &VaultDef, x: i32, y: i32, map: &Map, objects: &[Object],
+        keep_clear: &[(i32, i32)]) -> bool {
+    for tile_x in (x - 1)..(x + vault.width() + 1) {
+        for tile_y in (y - 1)..(y + vault.height() + 1) {
+            let tile = &map[tile_x as usize][tile_y as usize];
+            if tile.blocked || tile.terrain != Terrain::Floor {
+                return false;
+            }
+        }
+    }
+    let inside = |(tile_x, tile_y): (i32, i32)| {
+        tile_x >= x && tile_y >= y && tile_x < x + vault.width() && tile_y < y + vault.height()
+    };
+    !keep_clear.iter().any(|&tile| inside(tile)) &&
+        !objects.iter().any(|object| inside(object.pos()))
+}
+
+/// the tiles the vault changed, which are all but the ones it keeps
+fn stamp(vault: &VaultDef, x: i32, y: i32, map: &mut Map, objects: &mut Vec<Object>,
+         catalog: &Catalog, rng: &mut GameRng) -> Vec<(i32, i32)> {
+    let mut taken = vec![];
+    for (dy, row) in vault.cells.iter().enumerate() {
+        for (dx, cell) in row.iter().enumerate() {
+            let (tile_x, tile_y) = (x + dx as i32, y + dy as i32);
+            let tile = &mut map[tile_x as usize][tile_y as usize];
+            if *cell != Cell::Keep {
+                taken.push((tile_x, tile_y));
+            }
+            match *cell {
+                Cell::Keep => {}
+                Cell::Wall => *tile = Tile::wall(),
+                Cell::Floor => *tile = Tile::empty(),
+                Cell::Door => {
+                    *tile = Tile::door(if vault.locked { Door::Locked } else { Door::Closed });
+                }
+                Cell::Monster(ref name) => {
+                    *tile = Tile::empty();
+                    if let Some(monster) = catalog.monsters.monsters.iter()
+                        .find(|monster| &monster.name == name) {
+                        objects.push(monster.spawn_on_level(tile_x, tile_y, rng));
+                    }
+                }
+                Cell::Item(ref name) => {
+                    *tile = Tile::empty();
+                    if let Some(item) = catalog.items.items.iter().find(|item| &item.name == name) {
+                        objects.push(item.spawn(tile_x, tile_y));
+                    }
+                }
+            }
+        }
+    }
+    taken
+}